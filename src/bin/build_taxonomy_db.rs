@@ -2,7 +2,9 @@ use std::path::PathBuf;
 
 use clap::Parser;
 
-use taxonomy_lookup::{TaxonomyDatabaseConfig, TaxonomyDatabaseSource};
+use taxonomy_lookup::{
+    GtdbSource, SilvaSource, TaxonomyDatabaseConfig, TaxonomyDatabaseSource, TaxonomyResult,
+};
 
 /// Produce the taxonomy database
 #[derive(Parser, Debug)]
@@ -11,19 +13,64 @@ struct Args {
     /// Directory containing NCBI taxonomy information. This should be pulled from
     /// ftp.ncbi.nih.gov/pub/taxonomy/. It should include a file `taxdump.tar.gz`,
     /// and a directory `accession2taxid/` containing the files `prot.accession2taxid.gz`,
-    /// `nucl_wgs.accession2taxid.gz`, and `nucl_gb.accession2taxid.gz`.
+    /// `nucl_wgs.accession2taxid.gz`, and `nucl_gb.accession2taxid.gz`. With `--gtdb`, it
+    /// should instead contain GTDB's `bac120_taxonomy.tsv` and `ar53_taxonomy.tsv`. With
+    /// `--silva`, it should contain one SILVA taxonomy, e.g. `tax_slv_ssu_138.1.txt`, and its
+    /// accession map, e.g. `tax_slv_ssu_138.1.acc_taxid`.
     taxonomy_dir: PathBuf,
 
     /// Where to put the resulting database files. By default, this tool will place them in
     /// "$XDG_DATA_HOME/taxonomy_lookup/", which is where the library expects to find them by
     /// default.
     output_filename: Option<PathBuf>,
+
+    /// Take accessions from the gzipped GenBank or EMBL flatfiles found anywhere under this
+    /// directory, instead of from `accession2taxid/`. Only `taxdump.tar.gz`, and optionally the
+    /// `dead_*` accession2taxid files, are then needed from the taxonomy directory.
+    #[clap(long)]
+    flatfiles: Option<PathBuf>,
+
+    /// Build from GTDB's taxonomy files rather than NCBI's, indexing GTDB's genome
+    /// accessions. Taxon IDs are hashed from GTDB's labels, and recorded in
+    /// `gtdb_taxids.tsv`, as `label<TAB>taxid` lines, in the taxonomy directory. Keep that file
+    /// when moving to a new GTDB release, so that taxa keep their IDs.
+    #[clap(long, conflicts_with = "flatfiles")]
+    gtdb: bool,
+
+    /// Build from a SILVA taxonomy rather than NCBI's, indexing SILVA's sequences by their
+    /// keys, like AB000001.1.1500.
+    #[clap(long, conflicts_with_all = &["flatfiles", "gtdb"])]
+    silva: bool,
+
+    /// Also record every accession individually, so that strict lookups can rule out
+    /// accessions that never existed. This makes the database considerably larger.
+    #[clap(long)]
+    exact_membership: bool,
+
+    /// Also record which taxon each version of an accession belongs to, so that lookups can
+    /// ask for a specific version, e.g. U39076.1.
+    #[clap(long)]
+    accession_versions: bool,
 }
 
-fn main() -> std::io::Result<()> {
+fn main() -> TaxonomyResult<()> {
     let args = Args::parse();
+    let source = if args.gtdb {
+        TaxonomyDatabaseSource::FromSource(Box::new(GtdbSource::new(args.taxonomy_dir)))
+    } else if args.silva {
+        TaxonomyDatabaseSource::FromSource(Box::new(SilvaSource::new(args.taxonomy_dir)))
+    } else if let Some(flatfiles) = args.flatfiles {
+        TaxonomyDatabaseSource::FromFlatfiles {
+            taxonomy: args.taxonomy_dir,
+            flatfiles,
+        }
+    } else {
+        TaxonomyDatabaseSource::FromFiles(args.taxonomy_dir)
+    };
     let mut config = TaxonomyDatabaseConfig::new()
-        .source(TaxonomyDatabaseSource::FromFiles(args.taxonomy_dir.clone()));
+        .source(source)
+        .exact_membership(args.exact_membership)
+        .accession_versions(args.accession_versions);
     config = if let Some(p) = args.output_filename {
         config.location(p)
     } else {
@@ -4,6 +4,8 @@ use clap::Parser;
 use hex_literal::hex;
 use sha2::Digest;
 
+use taxonomy_lookup::TaxonomyResult;
+
 /// Download the taxonomy database
 #[derive(Parser, Debug)]
 #[clap(author, version, about)]
@@ -17,7 +19,7 @@ struct Args {
 const GZIP_URL: &str = "https://taxonomylookup.s3.amazonaws.com/taxonomy_db-2022-06-01.tar.gz";
 
 #[tokio::main]
-async fn main() -> std::io::Result<()> {
+async fn main() -> TaxonomyResult<()> {
     let args = Args::parse();
     let tmpdir = tempfile::Builder::new()
         .prefix("taxonomy_lookup")
@@ -41,8 +43,11 @@ async fn main() -> std::io::Result<()> {
     std::io::copy(&mut std::io::Cursor::new(content), &mut f)?;
     taxonomy_lookup::unzip_db(
         &dest,
-        args.target
-            .unwrap_or(xdg::BaseDirectories::with_prefix("taxonomy_lookup")?.get_data_home()),
+        args.target.unwrap_or(
+            xdg::BaseDirectories::with_prefix("taxonomy_lookup")
+                .map_err(std::io::Error::from)?
+                .get_data_home(),
+        ),
     )?;
     Ok(())
 }
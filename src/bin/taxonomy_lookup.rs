@@ -1,54 +1,416 @@
 use std::io::prelude::*;
-use std::io::Result;
 use std::path::PathBuf;
 
-use clap::Parser;
-use taxonomy_lookup::TaxonomyDatabaseConfig;
+use clap::{Parser, Subcommand};
+use taxonomy_lookup::{
+    diff, AccessionMatch, LookupExplanation, LookupOptions, NameMatchMode, Overlay, TaxonomyChange,
+    TaxonomyDatabase, TaxonomyDatabaseConfig, TaxonomyDatabaseSet, TaxonomyError, TaxonomyExport,
+    TaxonomyInfo, TaxonomyResult,
+};
 
 /// Look up accession numbers in the taxonomy database
 #[derive(Parser, Debug)]
 #[clap(author, version, about)]
 struct Args {
     /// Where to find the database files. By default, this tool will look for them in
-    /// "$XDG_DATA_HOME/taxonomy_lookup/".
-    #[clap(short, long)]
-    taxonomy_dir: Option<PathBuf>,
+    /// "$XDG_DATA_HOME/taxonomy_lookup/". Give this more than once to consult several databases
+    /// in order; each answer then says which database it came from. Name searches only use the
+    /// first database.
+    #[clap(short, long, global = true)]
+    taxonomy_dir: Vec<PathBuf>,
+
+    /// A tab-separated file of local taxa and accessions to layer on top of the database. Each
+    /// line is either "taxon <taxid> <parent taxid> <rank> <name>" or "accession <accession>
+    /// <taxid>". Local entries take precedence, and answers that use them are marked as local.
+    /// With several databases, the overlay is layered on each one whose taxa its parents exist
+    /// in.
+    #[clap(short, long, global = true)]
+    overlay: Option<PathBuf>,
 
     /// Accept line-separated accession numbers form stdin as well as from the command
     /// line.
     #[clap(short, long)]
     stdin: bool,
 
-    /// A list of accession numbers, e.g. U39076.1
+    /// Only answer for accessions that were actually present when the database was built,
+    /// rather than any that fall within a run of accessions sharing a taxon. This requires a
+    /// database built with `--exact-membership`.
+    #[clap(long)]
+    strict: bool,
+
+    /// Only answer for the exact version of each accession given, e.g. U39076.1 but not
+    /// U39076.2. This requires a database built with `--accession-versions`.
+    #[clap(long)]
+    exact_version: bool,
+
+    /// Explain how each accession was resolved: whether it was found exactly, within a recorded
+    /// range of accessions, or assumed from the accessions either side of it, and whether its
+    /// taxon has been merged into another.
+    #[clap(long)]
+    explain: bool,
+
+    /// A list of accession numbers, e.g. U39076.1. Prints the taxon ID and lineage of each,
+    /// tab-separated, followed by "merged from <taxid>" if the accession's taxon has been merged
+    /// into that one, and "local" if the answer came from the overlay.
     accession_numbers: Vec<String>,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Find the taxon IDs of organisms by name. Prints the taxon ID, matching name, name class
+    /// and rank of each match, tab-separated.
+    Name {
+        /// The name to look for, e.g. "Bacillus anthracis"
+        query: String,
+
+        /// Ignore case when comparing names.
+        #[clap(short, long)]
+        ignore_case: bool,
+
+        /// Match every name that starts with the query (ignoring case).
+        #[clap(short, long)]
+        prefix: bool,
+
+        /// Suggest the taxa with the most similar names, for misspelled or partially
+        /// remembered names. Prints the similarity score and lineage of each suggestion too.
+        #[clap(short, long, conflicts_with_all = &["ignore-case", "prefix"])]
+        fuzzy: bool,
+
+        /// How many suggestions to print in fuzzy mode.
+        #[clap(short = 'n', long, default_value = "10")]
+        limit: usize,
+    },
 
-    let mut config = TaxonomyDatabaseConfig::new().cache_size(10_000_000_000);
-    if let Some(tax_dir) = args.taxonomy_dir {
-        config = config.location(tax_dir);
+    /// Look up taxa by taxon ID. Taxa that NCBI has merged into others are looked up under
+    /// their current ID. Prints each as accession lookups do.
+    Taxon {
+        /// A list of taxon IDs, e.g. 562
+        taxa: Vec<u32>,
+    },
+
+    /// Compare two databases, e.g. before and after rebuilding from a new NCBI dump. Prints the
+    /// kind of each change, the taxon or accession it concerns, and its old and new values,
+    /// tab-separated. Accessions are only compared at the ends of each run of accessions sharing
+    /// a taxon, unless the databases were built with `--exact-membership`.
+    Diff {
+        /// Where to find the older database's files
+        old: PathBuf,
+
+        /// Where to find the newer database's files
+        new: PathBuf,
+
+        /// Print each change as a line of JSON instead, like
+        /// {"change":"parent_changed","taxon":562,"old":561,"new":543}.
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Write the first database out in NCBI's formats: nodes.dmp, names.dmp, merged.dmp and
+    /// delnodes.dmp, both loose and bundled as taxdump.tar.gz, and accession2taxid files under
+    /// accession2taxid/. Kraken2, Centrifuge and taxonkit can read the result, and
+    /// build_taxonomy_db can build a new database from it. Any overlay is left out.
+    Export {
+        /// Where to write the files
+        out_dir: PathBuf,
+
+        /// Only export the clade rooted at this taxon ID (along with its ancestors). This needs
+        /// a database built with `--exact-membership`, so that only the clade's own accessions
+        /// are written.
+        #[clap(long)]
+        subtree: Option<u32>,
+    },
+}
+
+/// Print an answer, prefixed with the database it came from if there's more than one.
+fn print_answer(dbs: &TaxonomyDatabaseSet, database: &str, answer: impl std::fmt::Display) {
+    if dbs.len() > 1 {
+        println!("{}\t{}", database, answer);
+    } else {
+        println!("{}", answer);
     }
+}
 
-    let db = config.build()?;
+/// The taxon ID and lineage, tab-separated, followed by "merged from <taxid>" if the taxon
+/// asked for has been merged into this one, and "local" if the answer used the overlay.
+fn info_line(info: &TaxonomyInfo) -> String {
+    let mut line = match info.lineage.leaf() {
+        Some(taxon) => format!("{}\t{}", taxon.id, info.lineage),
+        None => info.lineage.to_string(),
+    };
+    if let Some(merged_from) = info.merged_from {
+        line.push_str(&format!("\tmerged from {}", merged_from));
+    }
+    if info.local {
+        line.push_str("\tlocal");
+    }
+    line
+}
 
-    for an in args.accession_numbers {
-        if let Ok(info) = db.query_accession(&an) {
-            println!("{:?}", info);
+/// How an accession was resolved, in words.
+fn explanation_line(explanation: &LookupExplanation) -> String {
+    let mut line = match &explanation.accession_match {
+        AccessionMatch::Exact => "found exactly".to_string(),
+        AccessionMatch::Interpolated {
+            lower,
+            upper,
+            confirmed,
+        } => {
+            let confirmed = match confirmed {
+                Some(true) => ", and present in the source files",
+                Some(false) => ", but not present in the source files",
+                None => "",
+            };
+            format!("assumed from {} and {}{}", lower, upper, confirmed)
+        }
+        AccessionMatch::Range { start, end } => format!("within range {}-{}", start, end),
+        AccessionMatch::Local => "assigned by the overlay".to_string(),
+    };
+    if let Some(version) = explanation.version {
+        line.push_str(&format!("\tversion {}", version));
+    }
+    line.push_str(&format!("\ttaxon {}", explanation.taxon));
+    if let Some(merged_into) = explanation.merged_into {
+        line.push_str(&format!(", merged into {}", merged_into));
+    }
+    line
+}
+
+fn print_accession(dbs: &TaxonomyDatabaseSet, an: &str, options: LookupOptions, explain: bool) {
+    match dbs.query_accession_explained(an, options) {
+        Ok(answer) => {
+            let (info, explanation) = answer.answer;
+            let (_, wanted) = taxonomy_lookup::split_accession_version(an);
+            match (wanted, explanation.version) {
+                (Some(wanted), Some(found)) if wanted != found => {
+                    eprintln!("{} not found; using version {} instead", an, found)
+                }
+                (None, Some(found)) => eprintln!("{} matched version {}", an, found),
+                _ => {}
+            }
+            print_answer(dbs, answer.database, info_line(&info));
+            if explain {
+                print_answer(dbs, answer.database, explanation_line(&explanation));
+            }
+        }
+        Err(TaxonomyError::NotFound(_)) => eprintln!("Could not find {}", an),
+        Err(TaxonomyError::RetiredAccession { taxon, .. }) => {
+            eprintln!(
+                "{} has been retired; it last belonged to taxon {}",
+                an, taxon
+            )
+        }
+        Err(TaxonomyError::DeletedTaxon(taxon)) => {
+            eprintln!("{} belongs to taxon {}, which NCBI has deleted", an, taxon)
+        }
+        Err(e) => eprintln!("Error looking up {}: {}", an, e),
+    }
+}
+
+fn print_taxon(dbs: &TaxonomyDatabaseSet, taxon: u32) {
+    match dbs.query_taxon(taxon) {
+        Ok(answer) => print_answer(dbs, answer.database, info_line(&answer.answer)),
+        Err(TaxonomyError::UnknownTaxon(_)) => eprintln!("Could not find taxon {}", taxon),
+        Err(TaxonomyError::DeletedTaxon(_)) => {
+            eprintln!("Taxon {} has been deleted by NCBI", taxon)
+        }
+        Err(e) => eprintln!("Error looking up taxon {}: {}", taxon, e),
+    }
+}
+
+fn print_name_matches(
+    db: &TaxonomyDatabase,
+    query: &str,
+    mode: NameMatchMode,
+) -> TaxonomyResult<()> {
+    let matches = db.find_by_name(query, mode)?;
+    if matches.is_empty() {
+        eprintln!("Could not find {}", query);
+    }
+    for m in matches {
+        let class: &str = m.name_class.into();
+        println!("{}\t{}\t{}\t{}", m.taxon, m.name, class, m.rank);
+    }
+    Ok(())
+}
+
+fn print_fuzzy_matches(db: &TaxonomyDatabase, query: &str, limit: usize) -> TaxonomyResult<()> {
+    let matches = db.fuzzy_find_by_name(query, limit)?;
+    if matches.is_empty() {
+        eprintln!("Could not find anything resembling {}", query);
+    }
+    for m in matches {
+        let class: &str = m.name_class.into();
+        println!(
+            "{:.3}\t{}\t{}\t{}\t{}\t{}",
+            m.score, m.taxon, m.name, class, m.rank, m.lineage
+        );
+    }
+    Ok(())
+}
+
+/// The kind of a change, what it concerns, and its old and new values, if any.
+fn change_fields(change: &TaxonomyChange) -> (&'static str, String, String, String) {
+    match change {
+        TaxonomyChange::TaxonAdded { taxon } => (
+            "taxon_added",
+            taxon.to_string(),
+            String::new(),
+            String::new(),
+        ),
+        TaxonomyChange::TaxonMerged { taxon, into } => (
+            "taxon_merged",
+            taxon.to_string(),
+            String::new(),
+            into.to_string(),
+        ),
+        TaxonomyChange::TaxonDeleted { taxon } => (
+            "taxon_deleted",
+            taxon.to_string(),
+            String::new(),
+            String::new(),
+        ),
+        TaxonomyChange::TaxonRemoved { taxon } => (
+            "taxon_removed",
+            taxon.to_string(),
+            String::new(),
+            String::new(),
+        ),
+        TaxonomyChange::ParentChanged { taxon, old, new } => (
+            "parent_changed",
+            taxon.to_string(),
+            old.to_string(),
+            new.to_string(),
+        ),
+        TaxonomyChange::RankChanged { taxon, old, new } => (
+            "rank_changed",
+            taxon.to_string(),
+            old.to_string(),
+            new.to_string(),
+        ),
+        TaxonomyChange::NameChanged { taxon, old, new } => {
+            ("name_changed", taxon.to_string(), old.clone(), new.clone())
+        }
+        TaxonomyChange::AccessionChanged {
+            accession,
+            old,
+            new,
+        } => (
+            "accession_changed",
+            accession.clone(),
+            old.to_string(),
+            new.to_string(),
+        ),
+    }
+}
+
+fn print_changes(old: PathBuf, new: PathBuf, json: bool) -> TaxonomyResult<()> {
+    let open = |location| {
+        TaxonomyDatabaseConfig::new()
+            .cache_size(10_000_000_000)
+            .location(location)
+            .build()
+    };
+    for change in diff(&open(old)?, &open(new)?)? {
+        if json {
+            let line = serde_json::to_string(&change).map_err(std::io::Error::from)?;
+            println!("{}", line);
         } else {
-            eprintln!("Could not find {}", &an)
+            let (kind, subject, old_value, new_value) = change_fields(&change);
+            println!("{}\t{}\t{}\t{}", kind, subject, old_value, new_value);
+        }
+    }
+    Ok(())
+}
+
+fn main() -> TaxonomyResult<()> {
+    let args = Args::parse();
+
+    // Diffing opens its own databases.
+    if let Some(Command::Diff { old, new, json }) = args.command {
+        return print_changes(old, new, json);
+    }
+
+    let config = || TaxonomyDatabaseConfig::new().cache_size(10_000_000_000);
+    let mut dbs = TaxonomyDatabaseSet::new();
+    if args.taxonomy_dir.is_empty() {
+        dbs = dbs.add("default", config().build()?);
+    }
+    for tax_dir in args.taxonomy_dir {
+        let label = tax_dir.display().to_string();
+        dbs = dbs.add(label, config().location(tax_dir).build()?);
+    }
+    if let Some(overlay) = args.overlay {
+        let overlay = Overlay::from_tsv_file(overlay)?;
+        let mut first_error = None;
+        let mut attached = false;
+        for (_, db) in dbs.databases_mut() {
+            match db.set_overlay(overlay.clone()) {
+                Ok(()) => attached = true,
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        if let (false, Some(e)) = (attached, first_error) {
+            return Err(e);
+        }
+    }
+    let (_, db) = dbs
+        .databases()
+        .next()
+        .expect("there is always at least one database");
+
+    match args.command {
+        Some(Command::Name {
+            query,
+            ignore_case,
+            prefix,
+            fuzzy,
+            limit,
+        }) => {
+            if fuzzy {
+                return print_fuzzy_matches(db, &query, limit);
+            }
+            let mode = if prefix {
+                NameMatchMode::Prefix
+            } else if ignore_case {
+                NameMatchMode::CaseInsensitive
+            } else {
+                NameMatchMode::Exact
+            };
+            return print_name_matches(db, &query, mode);
+        }
+        Some(Command::Taxon { taxa }) => {
+            for taxon in taxa {
+                print_taxon(&dbs, taxon);
+            }
+            return Ok(());
+        }
+        Some(Command::Export { out_dir, subtree }) => {
+            let mut export = TaxonomyExport::new(db);
+            if let Some(subtree) = subtree {
+                export = export.subtree(subtree);
+            }
+            return export.write_dir(&out_dir);
         }
+        Some(Command::Diff { .. }) | None => {}
+    }
+
+    let options = LookupOptions {
+        strict: args.strict,
+        exact_version: args.exact_version,
+    };
+    for an in args.accession_numbers {
+        print_accession(&dbs, &an, options, args.explain);
     }
 
     if args.stdin {
         for an_maybe in std::io::stdin().lock().lines() {
             let an = an_maybe?;
-            if let Ok(info) = db.query_accession(&an) {
-                println!("{:?}", info);
-            } else {
-                eprintln!("Could not find {}", &an)
-            }
+            print_accession(&dbs, &an, options, args.explain);
         }
     }
     Ok(())
@@ -0,0 +1,180 @@
+use crate::error::{TaxonomyError, TaxonomyResult};
+use crate::rank::Rank;
+use crate::taxonomy_db::{merge_sorted_keys, TaxonomyDatabase};
+
+/// A difference between two databases, as found by [`diff`]. With the `serde` feature, a change
+/// is a flat object naming its kind in a "change" field, like
+/// `{"change":"parent_changed","taxon":562,"old":561,"new":543}`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "change", rename_all = "snake_case"))]
+pub enum TaxonomyChange {
+    /// The taxon is only in the newer database.
+    TaxonAdded {
+        taxon: u32,
+    },
+    /// The taxon has been merged into another.
+    TaxonMerged {
+        taxon: u32,
+        into: u32,
+    },
+    /// The taxon has been deleted.
+    TaxonDeleted {
+        taxon: u32,
+    },
+    /// The taxon is only in the older database, and the newer one doesn't say what became of
+    /// it.
+    TaxonRemoved {
+        taxon: u32,
+    },
+    ParentChanged {
+        taxon: u32,
+        old: u32,
+        new: u32,
+    },
+    RankChanged {
+        taxon: u32,
+        old: Rank,
+        new: Rank,
+    },
+    NameChanged {
+        taxon: u32,
+        old: String,
+        new: String,
+    },
+    /// The accession belongs to a different taxon. Accessions that only one of the databases
+    /// knows about aren't reported, and only stored accessions are compared; see [`diff`].
+    AccessionChanged {
+        accession: String,
+        old: u32,
+        new: u32,
+    },
+}
+
+impl TaxonomyChange {
+    /// The taxon that the change is about, unless it's about an accession.
+    pub fn taxon(&self) -> Option<u32> {
+        match self {
+            TaxonomyChange::TaxonAdded { taxon }
+            | TaxonomyChange::TaxonMerged { taxon, .. }
+            | TaxonomyChange::TaxonDeleted { taxon }
+            | TaxonomyChange::TaxonRemoved { taxon }
+            | TaxonomyChange::ParentChanged { taxon, .. }
+            | TaxonomyChange::RankChanged { taxon, .. }
+            | TaxonomyChange::NameChanged { taxon, .. } => Some(*taxon),
+            TaxonomyChange::AccessionChanged { .. } => None,
+        }
+    }
+}
+
+/// What has become of a taxon from another database, according to `db`.
+enum TaxonStatus {
+    Present,
+    Merged(u32),
+    Deleted,
+    Missing,
+}
+
+fn taxon_status(db: &TaxonomyDatabase, taxon: u32) -> TaxonomyResult<TaxonStatus> {
+    match db.resolve_taxid(taxon) {
+        Ok(current) if current == taxon => Ok(TaxonStatus::Present),
+        Ok(current) => Ok(TaxonStatus::Merged(current)),
+        Err(TaxonomyError::DeletedTaxon(_)) => Ok(TaxonStatus::Deleted),
+        Err(TaxonomyError::UnknownTaxon(_)) => Ok(TaxonStatus::Missing),
+        Err(e) => Err(e),
+    }
+}
+
+/// The taxon an accession belongs to, if `db` knows of it as a live accession.
+fn accession_taxid(db: &TaxonomyDatabase, accession: &str) -> TaxonomyResult<Option<u32>> {
+    match db.accession_taxid(accession) {
+        Ok(taxon) => Ok(Some(taxon)),
+        Err(TaxonomyError::NotFound(_) | TaxonomyError::RetiredAccession { .. }) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Compare two builds of the taxonomy database. Changes to taxa come first, ordered by taxon
+/// ID, followed by changes to accessions, ordered by accession. Overlays aren't compared.
+///
+/// Accessions are only compared where either database stores them, which for a database built
+/// from NCBI is at the ends of each run of accessions sharing a taxon. So when a whole run moves
+/// to another taxon, the change is reported at the run's ends, not for every accession in it.
+/// A database built with [`crate::TaxonomyDatabaseConfig::exact_membership`] stores, and so
+/// compares, every accession.
+pub fn diff(old: &TaxonomyDatabase, new: &TaxonomyDatabase) -> TaxonomyResult<Vec<TaxonomyChange>> {
+    let mut changes = vec![];
+
+    let mut old_taxa = old.taxa().collect::<TaxonomyResult<Vec<u32>>>()?;
+    old_taxa.sort_unstable();
+    for taxon in old_taxa {
+        match taxon_status(new, taxon)? {
+            TaxonStatus::Present => {}
+            TaxonStatus::Merged(into) => {
+                changes.push(TaxonomyChange::TaxonMerged { taxon, into });
+                continue;
+            }
+            TaxonStatus::Deleted => {
+                changes.push(TaxonomyChange::TaxonDeleted { taxon });
+                continue;
+            }
+            TaxonStatus::Missing => {
+                changes.push(TaxonomyChange::TaxonRemoved { taxon });
+                continue;
+            }
+        }
+        let (old_parent, new_parent) = (old.parent(taxon)?, new.parent(taxon)?);
+        if old_parent != new_parent {
+            changes.push(TaxonomyChange::ParentChanged {
+                taxon,
+                old: old_parent,
+                new: new_parent,
+            });
+        }
+        let (old_rank, new_rank) = (old.rank(taxon)?, new.rank(taxon)?);
+        if old_rank != new_rank {
+            changes.push(TaxonomyChange::RankChanged {
+                taxon,
+                old: old_rank,
+                new: new_rank,
+            });
+        }
+        let (old_name, new_name) = (old.name(taxon)?, new.name(taxon)?);
+        if old_name != new_name {
+            changes.push(TaxonomyChange::NameChanged {
+                taxon,
+                old: old_name,
+                new: new_name,
+            });
+        }
+    }
+
+    let mut new_taxa = new.taxa().collect::<TaxonomyResult<Vec<u32>>>()?;
+    new_taxa.sort_unstable();
+    for taxon in new_taxa {
+        // A taxon that the old database had merged or deleted has been revived; it counts as
+        // added too.
+        if !matches!(taxon_status(old, taxon)?, TaxonStatus::Present) {
+            changes.push(TaxonomyChange::TaxonAdded { taxon });
+        }
+    }
+    // Keep each taxon's changes together.
+    changes.sort_by_key(TaxonomyChange::taxon);
+
+    for accession in merge_sorted_keys([old.stored_accessions(), new.stored_accessions()]) {
+        let accession = accession?;
+        if let (Some(old_taxon), Some(new_taxon)) = (
+            accession_taxid(old, &accession)?,
+            accession_taxid(new, &accession)?,
+        ) {
+            if old_taxon != new_taxon {
+                changes.push(TaxonomyChange::AccessionChanged {
+                    accession,
+                    old: old_taxon,
+                    new: new_taxon,
+                });
+            }
+        }
+    }
+    Ok(changes)
+}
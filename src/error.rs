@@ -0,0 +1,112 @@
+use std::io;
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+/// Everything that can go wrong while building or querying a [`crate::TaxonomyDatabase`].
+#[derive(Debug, Error)]
+pub enum TaxonomyError {
+    /// The accession number isn't covered by the database.
+    #[error("accession {0:?} not found in database")]
+    NotFound(String),
+
+    /// NCBI has suppressed or withdrawn the accession.
+    #[error("accession {accession:?} has been retired; it last belonged to taxon {taxon}")]
+    RetiredAccession { accession: String, taxon: u32 },
+
+    /// The taxon ID isn't present in the taxonomy tree.
+    #[error("unknown taxon {0}")]
+    UnknownTaxon(u32),
+
+    /// The taxon ID used to exist, but NCBI has since deleted it (see delnodes.dmp).
+    #[error("taxon {0} has been deleted upstream")]
+    DeletedTaxon(u32),
+
+    /// A record that should exist (or should be well-formed) isn't. This indicates a problem
+    /// with the database itself, rather than with the query.
+    #[error("corrupted taxonomy database: bad entry for key {key} in tree {tree:?}")]
+    Corrupted { tree: &'static str, key: String },
+
+    /// A strict accession lookup was attempted on a database built without exact membership
+    /// information.
+    #[error("taxonomy database was built without exact accession membership")]
+    ExactMembershipUnavailable,
+
+    /// A version-specific accession lookup was attempted on a database built without accession
+    /// versions.
+    #[error("taxonomy database was built without accession versions")]
+    AccessionVersionsUnavailable,
+
+    /// The query needs an index that the database doesn't have, because it was built by an
+    /// older version of this crate. Rebuilding the database adds it.
+    #[error("taxonomy database was built without the {0} index")]
+    IndexUnavailable(&'static str),
+
+    /// Two GTDB taxa are recorded with the same taxon ID in `gtdb_taxids.tsv`; see
+    /// [`crate::GtdbSource`].
+    #[error("GTDB taxa {first:?} and {second:?} share taxon ID {taxid}")]
+    TaxonIdCollision {
+        first: String,
+        second: String,
+        taxid: u32,
+    },
+
+    /// An [`crate::Overlay`] taxon's parent is in neither the overlay nor the database.
+    #[error("overlay taxon {taxon} has unknown parent {parent}")]
+    OverlayUnknownParent { taxon: u32, parent: u32 },
+
+    /// Following parents from an [`crate::Overlay`] taxon leads back round to it, rather than up
+    /// to the root.
+    #[error("overlay taxon {0} is its own ancestor")]
+    OverlayCycle(u32),
+
+    /// The database on disk was produced by an incompatible version of this crate.
+    #[error("taxonomy database has incompatible version {found:?} (expected {expected:?})")]
+    IncompatibleVersion { found: String, expected: String },
+
+    /// One of the input files used to build the database couldn't be parsed.
+    #[error("{}:{line}: {message}", file.display())]
+    Source {
+        file: PathBuf,
+        line: usize,
+        message: String,
+    },
+
+    /// The underlying sled database failed.
+    #[error("storage error: {0}")]
+    Storage(#[from] sled::Error),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl TaxonomyError {
+    pub(crate) fn corrupted(tree: &'static str, key: impl std::fmt::Display) -> Self {
+        TaxonomyError::Corrupted {
+            tree,
+            key: key.to_string(),
+        }
+    }
+
+    pub(crate) fn parse(file: impl Into<PathBuf>, line: usize, message: impl Into<String>) -> Self {
+        TaxonomyError::Source {
+            file: file.into(),
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+pub type TaxonomyResult<T> = Result<T, TaxonomyError>;
+
+/// An ordering for merging sorted streams of results, as with
+/// [`itertools::Itertools::kmerge_by`]: values are compared with `less`, and errors come before
+/// everything, so that they surface as soon as they're read.
+pub(crate) fn errors_first<T>(
+    less: impl Fn(&T, &T) -> bool,
+) -> impl Fn(&TaxonomyResult<T>, &TaxonomyResult<T>) -> bool {
+    move |p, q| match (p, q) {
+        (Ok(p), Ok(q)) => less(p, q),
+        (p, _) => p.is_err(),
+    }
+}
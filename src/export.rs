@@ -0,0 +1,235 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::error::{TaxonomyError, TaxonomyResult};
+use crate::taxonomy_db::TaxonomyDatabase;
+
+/// The files that [`TaxonomyExport::write_dir`] bundles into taxdump.tar.gz.
+const TAXDUMP_FILES: [&str; 4] = ["nodes.dmp", "names.dmp", "merged.dmp", "delnodes.dmp"];
+
+/// Write one line of a .dmp file, whose fields are separated by "\t|\t" and end with "\t|".
+fn write_dmp_line<W: Write>(w: &mut W, fields: &[&str]) -> io::Result<()> {
+    writeln!(w, "{}\t|", fields.join("\t|\t"))
+}
+
+/// Create the file at `path`, and fill it with `write`.
+fn write_file(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> TaxonomyResult<()>,
+) -> TaxonomyResult<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    write(&mut w)?;
+    w.flush()?;
+    Ok(())
+}
+
+/// The header of the accession2taxid files we write. NCBI's live files also have a `gi`
+/// column, which we don't keep, and which nothing reading these files needs.
+const ACCESSION2TAXID_HEADER: &str = "accession\taccession.version\ttaxid";
+
+/// Writes a [`TaxonomyDatabase`] back out in NCBI's formats, as read by Kraken2, Centrifuge,
+/// taxonkit and [`crate::NcbiSource`]. Only what the database was built with is exported: its
+/// [`crate::Overlay`] is ignored, so local taxa and accessions are left out, and taxa the
+/// overlay replaces are written as they were built.
+///
+/// The database doesn't keep everything that NCBI's files hold: nodes.dmp fields other than the
+/// parent and rank are filled in with NCBI's defaults (division 8, "unassigned", and genetic
+/// code 1), and names have no unique name.
+pub struct TaxonomyExport {
+    db: TaxonomyDatabase,
+    subtree: Option<u32>,
+}
+
+impl TaxonomyExport {
+    pub fn new(db: &TaxonomyDatabase) -> Self {
+        TaxonomyExport {
+            db: db.without_overlay(),
+            subtree: None,
+        }
+    }
+
+    /// Only export the clade rooted at `taxon`, along with its ancestors, so that the result is
+    /// still a tree with the root at the top. Accessions and merged taxa are limited to those
+    /// belonging to the clade, and deleted taxa are left out. Exporting accessions then needs a
+    /// database that stores every accession; see [`TaxonomyExport::write_accessions`].
+    pub fn subtree(mut self, taxon: u32) -> Self {
+        self.subtree = Some(taxon);
+        self
+    }
+
+    /// The taxa to export, in ascending order.
+    fn taxa(&self) -> TaxonomyResult<Vec<u32>> {
+        let mut taxa = match self.subtree {
+            Some(subtree) => {
+                let mut taxa = self.db.ancestors(subtree)?;
+                for taxon in self.db.descendants(subtree)? {
+                    taxa.push(taxon?);
+                }
+                taxa
+            }
+            None => self.db.taxa().collect::<TaxonomyResult<Vec<u32>>>()?,
+        };
+        taxa.sort_unstable();
+        Ok(taxa)
+    }
+
+    /// Whether something belonging to `taxon` should be exported. Taxa that have since been
+    /// merged count as their replacement; deleted and unknown taxa only count when exporting
+    /// everything.
+    fn includes(&self, taxon: u32) -> TaxonomyResult<bool> {
+        let subtree = match self.subtree {
+            Some(subtree) => subtree,
+            None => return Ok(true),
+        };
+        match self.db.resolve_taxid(taxon) {
+            Ok(current) => self.db.is_descendant(current, subtree),
+            Err(TaxonomyError::UnknownTaxon(_) | TaxonomyError::DeletedTaxon(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether the database can export accessions as asked; see
+    /// [`TaxonomyExport::write_accessions`].
+    fn check_accessions(&self) -> TaxonomyResult<()> {
+        if self.subtree.is_some() && !self.db.stores_every_accession() {
+            return Err(TaxonomyError::ExactMembershipUnavailable);
+        }
+        Ok(())
+    }
+
+    /// Write nodes.dmp: each taxon's parent and rank.
+    pub fn write_nodes<W: Write>(&self, mut w: W) -> TaxonomyResult<()> {
+        for taxon in self.taxa()? {
+            let rank: &str = self.db.rank(taxon)?.into();
+            let taxon_str = taxon.to_string();
+            let parent_str = self.db.parent(taxon)?.to_string();
+            let defaults = ["", "8", "0", "1", "0", "0", "0", "0", "0", ""];
+            let mut fields = vec![taxon_str.as_str(), parent_str.as_str(), rank];
+            fields.extend(defaults);
+            write_dmp_line(&mut w, &fields)?;
+        }
+        Ok(())
+    }
+
+    /// Write names.dmp: every name of every taxon.
+    pub fn write_names<W: Write>(&self, mut w: W) -> TaxonomyResult<()> {
+        for taxon in self.taxa()? {
+            let taxon_str = taxon.to_string();
+            for (class, name) in self.db.names(taxon)? {
+                write_dmp_line(&mut w, &[&taxon_str, &name, "", class.into()])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write merged.dmp: taxa that have been merged into others, and their replacements.
+    pub fn write_merged<W: Write>(&self, mut w: W) -> TaxonomyResult<()> {
+        let mut merged = vec![];
+        for entry in self.db.merged_taxa() {
+            let (old, new) = entry?;
+            if self.includes(new)? {
+                merged.push((old, new));
+            }
+        }
+        merged.sort_unstable();
+        for (old, new) in merged {
+            write_dmp_line(&mut w, &[&old.to_string(), &new.to_string()])?;
+        }
+        Ok(())
+    }
+
+    /// Write delnodes.dmp: taxa that have been deleted. This is empty for a subtree.
+    pub fn write_deleted<W: Write>(&self, mut w: W) -> TaxonomyResult<()> {
+        if self.subtree.is_some() {
+            return Ok(());
+        }
+        let mut deleted = self
+            .db
+            .deleted_taxa()
+            .collect::<TaxonomyResult<Vec<u32>>>()?;
+        deleted.sort_unstable();
+        for taxon in deleted {
+            write_dmp_line(&mut w, &[&taxon.to_string()])?;
+        }
+        Ok(())
+    }
+
+    /// Write an accession2taxid file of live accessions. Unless the database was built with
+    /// [`crate::TaxonomyDatabaseConfig::exact_membership`], it only knows the first and last
+    /// accession of each run sharing a taxon, so only those are written; a database built from
+    /// a whole export answers just as the original does. Recorded ranges are written whole,
+    /// like "AAAA01000001-AAAA01099999", and versions only if the database recorded them.
+    ///
+    /// Leaving other taxa's runs out of a subtree would join up the runs either side of them, so
+    /// a subtree export fails with [`TaxonomyError::ExactMembershipUnavailable`] unless the
+    /// database stores every accession, and then writes just the clade's. A database built from
+    /// that still assumes that accessions between two of them belong to the same taxon, unless
+    /// it's queried strictly.
+    pub fn write_accessions<W: Write>(&self, mut w: W) -> TaxonomyResult<()> {
+        self.check_accessions()?;
+        writeln!(w, "{}", ACCESSION2TAXID_HEADER)?;
+        for record in self.db.accession_records() {
+            let (accession, version, taxon) = record?;
+            if !self.includes(taxon)? {
+                continue;
+            }
+            match version {
+                Some(version) => writeln!(w, "{0}\t{0}.{1}\t{2}", accession, version, taxon)?,
+                None => writeln!(w, "{0}\t{0}\t{1}", accession, taxon)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Write an accession2taxid file of retired accessions, with the taxa they last belonged
+    /// to.
+    pub fn write_dead_accessions<W: Write>(&self, mut w: W) -> TaxonomyResult<()> {
+        writeln!(w, "{}", ACCESSION2TAXID_HEADER)?;
+        for record in self.db.dead_accession_records() {
+            let (accession, taxon) = record?;
+            if self.includes(taxon)? {
+                writeln!(w, "{0}\t{0}\t{1}", accession, taxon)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write everything into `dir`, laid out as NCBI's taxonomy directory is: the .dmp files,
+    /// both loose and bundled into taxdump.tar.gz, and `accession2taxid/` holding
+    /// `export.accession2taxid.gz` and `dead_export.accession2taxid.gz`. The directory can then
+    /// be built into a new database with [`crate::TaxonomyDatabaseSource::FromFiles`].
+    pub fn write_dir(&self, dir: &Path) -> TaxonomyResult<()> {
+        // Fail before writing anything, rather than leave a partial export behind.
+        self.check_accessions()?;
+        let accession_dir = dir.join("accession2taxid");
+        fs::create_dir_all(&accession_dir)?;
+
+        write_file(&dir.join("nodes.dmp"), |w| self.write_nodes(w))?;
+        write_file(&dir.join("names.dmp"), |w| self.write_names(w))?;
+        write_file(&dir.join("merged.dmp"), |w| self.write_merged(w))?;
+        write_file(&dir.join("delnodes.dmp"), |w| self.write_deleted(w))?;
+
+        let taxdump_file = File::create(dir.join("taxdump.tar.gz"))?;
+        let mut taxdump = tar::Builder::new(GzEncoder::new(taxdump_file, Compression::default()));
+        for name in TAXDUMP_FILES {
+            taxdump.append_path_with_name(dir.join(name), name)?;
+        }
+        taxdump.into_inner()?.finish()?;
+
+        let gzipped = |name: &str| -> TaxonomyResult<_> {
+            let f = File::create(accession_dir.join(name))?;
+            Ok(GzEncoder::new(BufWriter::new(f), Compression::default()))
+        };
+        let mut w = gzipped("export.accession2taxid.gz")?;
+        self.write_accessions(&mut w)?;
+        w.finish()?.flush()?;
+        let mut w = gzipped("dead_export.accession2taxid.gz")?;
+        self.write_dead_accessions(&mut w)?;
+        w.finish()?.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,420 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use itertools::Itertools;
+
+use crate::error::{errors_first, TaxonomyError, TaxonomyResult};
+use crate::source::open_gzipped;
+use crate::taxonomy_db::split_accession_version;
+
+/// The accessions of a single flatfile record, and the taxon of its source feature.
+#[derive(Default)]
+struct Entry {
+    accessions: Vec<String>,
+    version: Option<u32>,
+    taxid: Option<u32>,
+}
+
+impl Entry {
+    /// Produce (accession, version, taxid) triples for the record, if it named a taxon. Only the
+    /// primary (first) accession carries the record's version; secondary accessions and ranges
+    /// don't have one.
+    fn finish(self, records: &mut Vec<(String, Option<u32>, u32)>) {
+        let taxid = match self.taxid {
+            Some(taxid) => taxid,
+            None => return,
+        };
+        for (i, accession) in self.accessions.into_iter().enumerate() {
+            let version = if i == 0 { self.version } else { None };
+            records.push((accession, version, taxid));
+        }
+    }
+}
+
+/// Pull the taxon ID out of a `/db_xref="taxon:1234"` qualifier.
+fn parse_taxon_xref(line: &str) -> Option<u32> {
+    let rest = &line[line.find("/db_xref=\"taxon:")? + "/db_xref=\"taxon:".len()..];
+    rest.split('"').next()?.parse::<u32>().ok()
+}
+
+/// Read a GenBank or EMBL flatfile, producing (accession, version, taxid) triples in file
+/// order. Secondary accessions are included, and accession ranges like
+/// "AAAA01000001-AAAA01099999" are kept whole. Records without a taxon are skipped.
+pub fn read_flatfile<R: BufRead>(
+    f: R,
+    file: &Path,
+) -> TaxonomyResult<Vec<(String, Option<u32>, u32)>> {
+    let mut records = vec![];
+    let mut entry = Entry::default();
+    // GenBank ACCESSION lines can continue onto following lines indented by 12 spaces.
+    let mut in_accession = false;
+    for (i, l) in f.lines().enumerate() {
+        let line = l.map_err(|e| TaxonomyError::parse(file, i + 1, e.to_string()))?;
+        if line.starts_with("//") {
+            std::mem::take(&mut entry).finish(&mut records);
+            in_accession = false;
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("ACCESSION") {
+            // GenBank: "ACCESSION   U39076 U39077-U39079"
+            entry
+                .accessions
+                .extend(rest.split_whitespace().map(String::from));
+            in_accession = true;
+            continue;
+        }
+        if in_accession && line.starts_with("            ") {
+            entry
+                .accessions
+                .extend(line.split_whitespace().map(String::from));
+            continue;
+        }
+        in_accession = false;
+        if let Some(rest) = line.strip_prefix("VERSION") {
+            // GenBank: "VERSION     U39076.1"
+            if let Some(accession) = rest.split_whitespace().next() {
+                entry.version = split_accession_version(accession).1;
+            }
+        } else if let Some(rest) = line.strip_prefix("AC   ") {
+            // EMBL: "AC   X56734; X56735;". Lines starting with "*" carry other identifiers.
+            if !rest.starts_with('*') {
+                entry.accessions.extend(
+                    rest.split(';')
+                        .map(str::trim)
+                        .filter(|an| !an.is_empty())
+                        .map(String::from),
+                );
+            }
+        } else if let Some(rest) = line.strip_prefix("ID   ") {
+            // EMBL: "ID   X56734; SV 1; linear; ..."
+            entry.version = rest
+                .split(';')
+                .map(str::trim)
+                .find_map(|field| field.strip_prefix("SV "))
+                .and_then(|v| v.trim().parse::<u32>().ok());
+        } else if entry.taxid.is_none() {
+            // Only the first source feature counts.
+            entry.taxid = parse_taxon_xref(&line);
+        }
+    }
+    Ok(records)
+}
+
+/// Every gzipped file under `dir`, at any depth, in a consistent order.
+fn find_gzipped(dir: &Path) -> TaxonomyResult<Vec<PathBuf>> {
+    let mut paths = vec![];
+    let mut dirs = vec![dir.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        for e in std::fs::read_dir(&dir)? {
+            let path = e?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "gz") {
+                paths.push(path);
+            }
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// How many sorted runs are merged at once. Runs are merged in passes, so that however many
+/// files there are, no more than this many runs are open at a time.
+const MERGE_WIDTH: usize = 64;
+
+/// Write sorted records to `path`, one "accession\tversion\ttaxid" line each, so that they
+/// needn't be held in memory until every file has been read.
+fn spill(
+    records: impl IntoIterator<Item = TaxonomyResult<(String, Option<u32>, u32)>>,
+    path: &Path,
+) -> TaxonomyResult<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    for record in records {
+        let (accession, version, taxid) = record?;
+        let version = version.map(|v| v.to_string()).unwrap_or_default();
+        writeln!(w, "{}\t{}\t{}", accession, version, taxid)?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+/// Parse a line written by `spill`.
+fn parse_spilled(line: &str) -> Option<(String, Option<u32>, u32)> {
+    let mut fields = line.split('\t');
+    let accession = fields.next()?.to_string();
+    let version = match fields.next()? {
+        "" => None,
+        version => Some(version.parse().ok()?),
+    };
+    let taxid = fields.next()?.parse().ok()?;
+    Some((accession, version, taxid))
+}
+
+/// Read back the records written by `spill`.
+fn read_spilled(
+    path: &Path,
+) -> TaxonomyResult<impl Iterator<Item = TaxonomyResult<(String, Option<u32>, u32)>>> {
+    Ok(BufReader::new(File::open(path)?).lines().map(|l| {
+        let line = l?;
+        parse_spilled(&line).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("corrupted spilled flatfile record {:?}", line),
+            )
+            .into()
+        })
+    }))
+}
+
+/// Merge spilled runs, each sorted by accession, into one sorted stream. Ties are broken by the
+/// order of `runs`, so that the first run to mention an accession still wins.
+fn merge_runs(
+    runs: &[PathBuf],
+) -> TaxonomyResult<impl Iterator<Item = TaxonomyResult<(String, Option<u32>, u32)>>> {
+    let mut readers = vec![];
+    for (i, run) in runs.iter().enumerate() {
+        readers.push(read_spilled(run)?.map(move |record| record.map(|record| (i, record))));
+    }
+    Ok(readers
+        .into_iter()
+        .kmerge_by(errors_first(|(i, p): &(usize, (String, _, _)), (j, q)| {
+            (&p.0, i) < (&q.0, j)
+        }))
+        .map(|record| record.map(|(_, record)| record)))
+}
+
+/// Records read back from runs spilled into a temporary directory, which is removed once
+/// they've been dropped.
+struct SpilledRecords<I> {
+    records: I,
+    _dir: tempfile::TempDir,
+}
+
+impl<I: Iterator> Iterator for SpilledRecords<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        self.records.next()
+    }
+}
+
+/// Read every gzipped GenBank or EMBL flatfile under `dir`, spreading the files across all
+/// available cores, and produce (accession, version, taxid) triples sorted by accession. Where
+/// an accession appears more than once, the records keep the order of the files they came from.
+/// Each file is sorted on its own and spilled to a temporary file, and the sorted runs are then
+/// merged, so only one file's records per core are in memory at once.
+pub(crate) fn read_flatfile_dir(
+    dir: &Path,
+) -> TaxonomyResult<impl Iterator<Item = TaxonomyResult<(String, Option<u32>, u32)>>> {
+    sort_flatfile_dir(dir, MERGE_WIDTH)
+}
+
+/// [`read_flatfile_dir`], merging `merge_width` runs at a time.
+fn sort_flatfile_dir(
+    dir: &Path,
+    merge_width: usize,
+) -> TaxonomyResult<impl Iterator<Item = TaxonomyResult<(String, Option<u32>, u32)>>> {
+    assert!(
+        merge_width >= 2,
+        "merging fewer than two runs never finishes"
+    );
+    let paths = find_gzipped(dir)?;
+    let spill_dir = tempfile::tempdir()?;
+    let run_path = |i: usize| spill_dir.path().join(i.to_string());
+    let next_path = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(paths.len()));
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let i = next_path.fetch_add(1, Ordering::Relaxed);
+                let path = match paths.get(i) {
+                    Some(path) => path,
+                    None => break,
+                };
+                let result = open_gzipped(path)
+                    .and_then(|f| read_flatfile(BufReader::new(f), path))
+                    .and_then(|mut records| {
+                        // A stable sort, so that records for the same accession keep their
+                        // order within the file.
+                        records.sort_by(|p, q| p.0.cmp(&q.0));
+                        spill(records.into_iter().map(Ok), &run_path(i))
+                    });
+                results.lock().unwrap().push((i, result));
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(i, _)| *i);
+    let mut runs = vec![];
+    for (i, result) in results {
+        result?;
+        runs.push(run_path(i));
+    }
+    // Merging neighbouring runs keeps them in file order, so ties still go to the first file.
+    let mut next_run = paths.len();
+    while runs.len() > merge_width {
+        let mut merged = vec![];
+        for batch in runs.chunks(merge_width) {
+            let path = run_path(next_run);
+            next_run += 1;
+            spill(merge_runs(batch)?, &path)?;
+            for run in batch {
+                std::fs::remove_file(run)?;
+            }
+            merged.push(path);
+        }
+        runs = merged;
+    }
+    Ok(SpilledRecords {
+        records: merge_runs(&runs)?,
+        _dir: spill_dir,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(text: &str) -> Vec<(String, Option<u32>, u32)> {
+        read_flatfile(text.as_bytes(), Path::new("test.seq")).unwrap()
+    }
+
+    fn record(accession: &str, version: Option<u32>, taxid: u32) -> (String, Option<u32>, u32) {
+        (accession.to_string(), version, taxid)
+    }
+
+    #[test]
+    fn genbank_accessions_and_version() {
+        let text = "\
+LOCUS       U39076                  1000 bp    DNA     linear   VRL 01-JAN-2000
+ACCESSION   U39076 U40000
+            U40001-U40003
+            U40010
+VERSION     U39076.3
+FEATURES             Location/Qualifiers
+     source          1..1000
+                     /db_xref=\"taxon:10244\"
+     CDS             1..100
+                     /db_xref=\"taxon:9999\"
+//
+";
+        assert_eq!(
+            read(text),
+            vec![
+                record("U39076", Some(3), 10244),
+                record("U40000", None, 10244),
+                record("U40001-U40003", None, 10244),
+                record("U40010", None, 10244),
+            ]
+        );
+    }
+
+    #[test]
+    fn genbank_continuation_ends_at_next_keyword() {
+        let text = "\
+ACCESSION   U39076
+VERSION     U39076.1
+KEYWORDS    .
+            U99999
+                     /db_xref=\"taxon:10244\"
+//
+";
+        assert_eq!(read(text), vec![record("U39076", Some(1), 10244)]);
+    }
+
+    #[test]
+    fn records_without_a_taxon_are_skipped() {
+        let text = "\
+ACCESSION   X00001
+VERSION     X00001.1
+//
+ACCESSION   X00002
+VERSION     X00002.4
+                     /db_xref=\"taxon:562\"
+//
+";
+        assert_eq!(read(text), vec![record("X00002", Some(4), 562)]);
+    }
+
+    #[test]
+    fn embl_accessions_and_version() {
+        let text = "\
+ID   Y56734; SV 2; linear; mRNA; STD; PLN; 1859 BP.
+AC   Y56734; Y56735;
+AC   DDDD01000001-DDDD01000099;
+AC   * _abc
+FT   source          1..1859
+FT                   /db_xref=\"taxon:1392\"
+//
+ID   Y60000; SV 1; linear; mRNA; STD; PLN; 100 BP.
+AC   Y60000;
+//
+";
+        assert_eq!(
+            read(text),
+            vec![
+                record("Y56734", Some(2), 1392),
+                record("Y56735", None, 1392),
+                record("DDDD01000001-DDDD01000099", None, 1392),
+            ]
+        );
+    }
+
+    #[test]
+    fn spilled_records_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run");
+        let records = vec![record("A00001", Some(2), 562), record("A00002", None, 561)];
+        spill(records.clone().into_iter().map(Ok), &path).unwrap();
+        let read_back = read_spilled(&path)
+            .unwrap()
+            .collect::<TaxonomyResult<Vec<_>>>()
+            .unwrap();
+        assert_eq!(read_back, records);
+    }
+
+    /// Write a gzipped GenBank flatfile to `path`, with a record for each accession and taxon.
+    fn write_flatfile(path: &Path, records: &[(String, u32)]) {
+        let f = File::create(path).unwrap();
+        let mut w = flate2::write::GzEncoder::new(f, flate2::Compression::fast());
+        for (accession, taxid) in records {
+            writeln!(w, "ACCESSION   {}", accession).unwrap();
+            writeln!(w, "FEATURES             Location/Qualifiers").unwrap();
+            writeln!(w, "                     /db_xref=\"taxon:{}\"", taxid).unwrap();
+            writeln!(w, "//").unwrap();
+        }
+        w.finish().unwrap();
+    }
+
+    #[test]
+    fn many_files_are_merged_in_passes() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = 23;
+        let mut expected = vec![];
+        for file in 0..files {
+            // Every file mentions A00000, and each claims it for a different taxon, so only the
+            // first file's record should come first.
+            let mut records = vec![(format!("A{:05}", 0), file)];
+            records.extend((0..5).map(|n| (format!("A{:05}", 1 + n * files + file), file)));
+            records.reverse();
+            write_flatfile(&dir.path().join(format!("{:02}.seq.gz", file)), &records);
+            expected.extend(records);
+        }
+        // Sort by accession, keeping file order among equal accessions.
+        expected.sort_by(|p, q| p.0.cmp(&q.0));
+
+        let sorted = sort_flatfile_dir(dir.path(), 3)
+            .unwrap()
+            .map(|record| record.map(|(accession, _, taxid)| (accession, taxid)))
+            .collect::<TaxonomyResult<Vec<_>>>()
+            .unwrap();
+        assert_eq!(sorted, expected);
+        assert_eq!(sorted[0], ("A00000".to_string(), 0));
+    }
+}
@@ -0,0 +1,344 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::{TaxonomyError, TaxonomyResult};
+use crate::name_class::NameClass;
+use crate::rank::Rank;
+use crate::source::{open_maybe_gzipped, SourceStream, TaxonomySource};
+use crate::taxonomy_db::split_accession_version;
+
+/// GTDB has no root of its own, so one is made up, with NCBI's root ID.
+const GTDB_ROOT: u32 = 1;
+
+/// The file in the GTDB directory that records the ID given to each taxon, so that later
+/// releases can reuse them; see [`GtdbSource`].
+const TAXIDS_FILE: &str = "gtdb_taxids.tsv";
+
+/// The taxonomy described by one or more GTDB taxonomy files.
+#[derive(Default)]
+struct GtdbTaxonomy {
+    /// Labels like "g__Escherichia", with their parent's label (`None` for domains).
+    nodes: BTreeMap<String, Option<String>>,
+    /// (bare accession, version, label of the taxon the genome belongs to).
+    genomes: Vec<(String, Option<u32>, String)>,
+    /// Each label's taxon ID, as read from `TAXIDS_FILE`. Labels of taxa that have since gone
+    /// keep theirs, so that it isn't given to anything else.
+    taxids: BTreeMap<String, u32>,
+}
+
+impl GtdbTaxonomy {
+    /// Give every node that doesn't have a taxon ID yet a hash of its label, or, if another
+    /// taxon already has that, the next free ID above it. New labels are taken in sorted order,
+    /// so the result only depends on the labels and the IDs already given out. Returns whether
+    /// any were added. Two labels already sharing an ID is an error.
+    fn assign_taxids(&mut self) -> TaxonomyResult<bool> {
+        let mut owners = HashMap::with_capacity(self.nodes.len());
+        for (label, &taxid) in &self.taxids {
+            if let Some(owner) = owners.insert(taxid, label.as_str()) {
+                return Err(TaxonomyError::TaxonIdCollision {
+                    first: owner.to_string(),
+                    second: label.clone(),
+                    taxid,
+                });
+            }
+        }
+        let mut added = vec![];
+        for label in self.nodes.keys() {
+            if self.taxids.contains_key(label) {
+                continue;
+            }
+            let mut taxid = fnv1a(label.as_bytes());
+            while taxid <= GTDB_ROOT || owners.contains_key(&taxid) {
+                taxid = taxid.wrapping_add(1);
+            }
+            owners.insert(taxid, label.as_str());
+            added.push((label.clone(), taxid));
+        }
+        let changed = !added.is_empty();
+        self.taxids.extend(added);
+        Ok(changed)
+    }
+}
+
+/// 32-bit FNV-1a, which unlike std's hashers is guaranteed not to change between releases.
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c9dc5, |hash, &b| {
+        (hash ^ u32::from(b)).wrapping_mul(0x01000193)
+    })
+}
+
+/// Map a GTDB rank prefix, like the "g" of "g__Escherichia", onto a rank.
+fn gtdb_rank(prefix: &str) -> Option<Rank> {
+    match prefix {
+        "d" => Some(Rank::Superkingdom),
+        "p" => Some(Rank::Phylum),
+        "c" => Some(Rank::Class),
+        "o" => Some(Rank::Order),
+        "f" => Some(Rank::Family),
+        "g" => Some(Rank::Genus),
+        "s" => Some(Rank::Species),
+        _ => None,
+    }
+}
+
+/// The rank and name of a GTDB label like "s__Escherichia coli".
+fn split_label(label: &str) -> Option<(Rank, &str)> {
+    let (prefix, name) = label.split_once("__")?;
+    Some((gtdb_rank(prefix)?, name))
+}
+
+/// Read a GTDB taxonomy file into `taxonomy`. Each line holds a genome accession, like
+/// "RS_GCF_000005845.2", and its lineage, like "d__Bacteria;p__Proteobacteria;...".
+fn read_gtdb_taxonomy_file<R: Read>(
+    f: R,
+    file: &Path,
+    taxonomy: &mut GtdbTaxonomy,
+) -> TaxonomyResult<()> {
+    for (i, l) in BufReader::new(f).lines().enumerate() {
+        let line = l?;
+        let (genome, lineage) = line
+            .split_once('\t')
+            .ok_or_else(|| TaxonomyError::parse(file, i + 1, "Invalid line"))?;
+        let mut parent: Option<&str> = None;
+        for label in lineage.trim_end().split(';') {
+            let (_, name) = split_label(label).ok_or_else(|| {
+                TaxonomyError::parse(file, i + 1, format!("Invalid taxon: {:?}", label))
+            })?;
+            // Older releases leave some ranks unnamed, e.g. "s__"; genomes then belong to the
+            // closest named ancestor.
+            if name.is_empty() {
+                continue;
+            }
+            taxonomy
+                .nodes
+                .entry(label.to_string())
+                .or_insert_with(|| parent.map(String::from));
+            parent = Some(label);
+        }
+        let parent = parent.ok_or_else(|| TaxonomyError::parse(file, i + 1, "Empty lineage"))?;
+        // GTDB marks which database a genome came from with "RS_" (RefSeq) or "GB_" (GenBank).
+        let accession = genome
+            .strip_prefix("RS_")
+            .or_else(|| genome.strip_prefix("GB_"))
+            .unwrap_or(genome);
+        let (bare_acc, version) = split_accession_version(accession);
+        taxonomy
+            .genomes
+            .push((bare_acc.to_string(), version, parent.to_string()));
+    }
+    Ok(())
+}
+
+/// Read a file of "label\ttaxid" lines, like "s__Escherichia coli\t4000000001", into
+/// `taxonomy`.
+fn read_taxids_file<R: Read>(f: R, file: &Path, taxonomy: &mut GtdbTaxonomy) -> TaxonomyResult<()> {
+    for (i, l) in BufReader::new(f).lines().enumerate() {
+        let line = l?;
+        let (label, taxid) = line
+            .split_once('\t')
+            .ok_or_else(|| TaxonomyError::parse(file, i + 1, "Invalid line"))?;
+        let taxid = taxid
+            .trim_end()
+            .parse::<u32>()
+            .ok()
+            .filter(|&taxid| taxid > GTDB_ROOT)
+            .ok_or_else(|| TaxonomyError::parse(file, i + 1, "Invalid taxon ID"))?;
+        taxonomy.taxids.insert(label.to_string(), taxid);
+    }
+    Ok(())
+}
+
+/// GTDB's taxonomy, built from the `bac120_taxonomy*.tsv` and `ar53_taxonomy*.tsv` files (or
+/// `ar122_taxonomy*.tsv`, in older releases) in a directory, optionally gzipped. GTDB has no
+/// taxon IDs, so they are synthesized by hashing each taxon's label, moving on to the next free
+/// ID if two labels collide. The IDs given out are recorded in a `gtdb_taxids.tsv` file in the
+/// same directory, as "label\ttaxid" lines like "s__Escherichia coli\t4000000001", and reused
+/// from then on; keep that file from one release to the next so that IDs don't change. Genome
+/// accessions
+/// (GCA_ and GCF_) are indexed without GTDB's "RS_" and "GB_" prefixes, and only the genomes
+/// listed are answered for.
+pub struct GtdbSource {
+    gtdb_dir: PathBuf,
+}
+
+impl GtdbSource {
+    pub fn new(gtdb_dir: PathBuf) -> Self {
+        GtdbSource { gtdb_dir }
+    }
+
+    fn taxonomy_paths(&self) -> TaxonomyResult<Vec<PathBuf>> {
+        let mut paths = vec![];
+        for e in std::fs::read_dir(&self.gtdb_dir)? {
+            let path = e?.path();
+            let name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name,
+                None => continue,
+            };
+            if ["bac120_taxonomy", "ar53_taxonomy", "ar122_taxonomy"]
+                .iter()
+                .any(|prefix| name.starts_with(prefix))
+                && (name.ends_with(".tsv") || name.ends_with(".tsv.gz"))
+            {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// Read the taxonomy, giving any new taxa IDs and recording them in `TAXIDS_FILE`.
+    fn read(&self) -> TaxonomyResult<GtdbTaxonomy> {
+        let mut taxonomy = GtdbTaxonomy::default();
+        let taxids_path = self.gtdb_dir.join(TAXIDS_FILE);
+        match File::open(&taxids_path) {
+            Ok(f) => read_taxids_file(f, &taxids_path, &mut taxonomy)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        for path in self.taxonomy_paths()? {
+            read_gtdb_taxonomy_file(open_maybe_gzipped(&path)?, &path, &mut taxonomy)?;
+        }
+        if taxonomy.assign_taxids()? {
+            // Written whole and then moved into place, so that a failed write can't lose IDs.
+            let mut f = tempfile::NamedTempFile::new_in(&self.gtdb_dir)?;
+            let mut w = BufWriter::new(&mut f);
+            for (label, taxid) in &taxonomy.taxids {
+                writeln!(w, "{}\t{}", label, taxid)?;
+            }
+            w.flush()?;
+            drop(w);
+            f.persist(&taxids_path).map_err(|e| e.error)?;
+        }
+        Ok(taxonomy)
+    }
+}
+
+impl TaxonomySource for GtdbSource {
+    fn nodes(&self) -> TaxonomyResult<SourceStream<'_, (u32, u32, Rank)>> {
+        let taxonomy = self.read()?;
+        let taxids = &taxonomy.taxids;
+        let mut nodes = vec![Ok((GTDB_ROOT, GTDB_ROOT, Rank::NoRank))];
+        for (label, parent) in &taxonomy.nodes {
+            let (rank, _) = split_label(label).expect("labels were checked when read");
+            let parent = match parent {
+                Some(parent) => taxids[parent.as_str()],
+                None => GTDB_ROOT,
+            };
+            nodes.push(Ok((taxids[label.as_str()], parent, rank)));
+        }
+        Ok(Box::new(nodes.into_iter()))
+    }
+
+    fn names(&self) -> TaxonomyResult<SourceStream<'_, (u32, NameClass, String)>> {
+        let taxonomy = self.read()?;
+        let taxids = &taxonomy.taxids;
+        let mut names = vec![Ok((
+            GTDB_ROOT,
+            NameClass::ScientificName,
+            "root".to_string(),
+        ))];
+        for label in taxonomy.nodes.keys() {
+            let (_, name) = split_label(label).expect("labels were checked when read");
+            names.push(Ok((
+                taxids[label.as_str()],
+                NameClass::ScientificName,
+                name.to_string(),
+            )));
+        }
+        Ok(Box::new(names.into_iter()))
+    }
+
+    fn accessions(&self) -> TaxonomyResult<SourceStream<'_, (String, Option<u32>, u32)>> {
+        let taxonomy = self.read()?;
+        let taxids = &taxonomy.taxids;
+        let mut accessions = taxonomy
+            .genomes
+            .iter()
+            .map(|(bare_acc, version, label)| (bare_acc.clone(), *version, taxids[label.as_str()]))
+            .collect::<Vec<_>>();
+        // A stable sort, so that the first file to mention a genome still wins.
+        accessions.sort_by(|p, q| p.0.cmp(&q.0));
+        Ok(Box::new(accessions.into_iter().map(Ok)))
+    }
+
+    /// GTDB only has a sample of genomes, so the accessions between two of them needn't belong
+    /// to the same taxon, or exist at all.
+    fn interpolate_accessions(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// These two labels have the same FNV-1a hash.
+    const COLLIDING: [&str; 2] = ["s__Taxon 1338406", "s__Taxon 834998"];
+
+    fn taxonomy(labels: &[&str], taxids: &[(&str, u32)]) -> GtdbTaxonomy {
+        GtdbTaxonomy {
+            nodes: labels
+                .iter()
+                .map(|label| (label.to_string(), None))
+                .collect(),
+            genomes: vec![],
+            taxids: taxids
+                .iter()
+                .map(|&(label, taxid)| (label.to_string(), taxid))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn collisions_move_to_the_next_free_id() {
+        assert_eq!(
+            fnv1a(COLLIDING[0].as_bytes()),
+            fnv1a(COLLIDING[1].as_bytes())
+        );
+        let hash = fnv1a(COLLIDING[0].as_bytes());
+        let mut taxonomy = taxonomy(&COLLIDING, &[]);
+        assert!(taxonomy.assign_taxids().unwrap());
+        // Labels are taken in sorted order, whatever order they were read in.
+        assert_eq!(taxonomy.taxids[COLLIDING[0]], hash);
+        assert_eq!(taxonomy.taxids[COLLIDING[1]], hash + 1);
+    }
+
+    #[test]
+    fn recorded_ids_are_kept() {
+        let hash = fnv1a(COLLIDING[0].as_bytes());
+        // The later label got the hash in an earlier release, and a taxon that has since gone
+        // had the next ID up.
+        let mut taxonomy = taxonomy(&COLLIDING, &[(COLLIDING[1], hash), ("s__Gone", hash + 1)]);
+        assert!(taxonomy.assign_taxids().unwrap());
+        assert_eq!(taxonomy.taxids[COLLIDING[1]], hash);
+        assert_eq!(taxonomy.taxids[COLLIDING[0]], hash + 2);
+        assert_eq!(taxonomy.taxids["s__Gone"], hash + 1);
+        assert!(!taxonomy.assign_taxids().unwrap());
+    }
+
+    #[test]
+    fn shared_recorded_ids_are_an_error() {
+        let mut taxonomy = taxonomy(&["g__A", "g__B"], &[("g__A", 5), ("g__B", 5)]);
+        assert!(matches!(
+            taxonomy.assign_taxids(),
+            Err(TaxonomyError::TaxonIdCollision { taxid: 5, .. })
+        ));
+    }
+
+    #[test]
+    fn bad_taxid_lines_are_reported() {
+        for text in [
+            "g__A\t5\ng__B\n",
+            "g__A\t5\ng__B\tx\n",
+            "g__A\t5\ng__B\t1\n",
+        ] {
+            let mut taxonomy = GtdbTaxonomy::default();
+            assert!(matches!(
+                read_taxids_file(text.as_bytes(), Path::new("gtdb_taxids.tsv"), &mut taxonomy),
+                Err(TaxonomyError::Source { line: 2, .. })
+            ));
+        }
+    }
+}
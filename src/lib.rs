@@ -1,5 +1,41 @@
+pub mod diff;
+pub use diff::*;
+
+pub mod error;
+pub use error::*;
+
+pub mod export;
+pub use export::*;
+
+pub mod flatfile;
+pub use flatfile::*;
+
+pub mod gtdb;
+pub use gtdb::*;
+
+pub mod lineage;
+pub use lineage::*;
+
+pub mod name_class;
+pub use name_class::*;
+
+pub mod ncbi;
+pub use ncbi::*;
+
+pub mod overlay;
+pub use overlay::*;
+
 pub mod rank;
 pub use rank::*;
 
+pub mod silva;
+pub use silva::*;
+
+pub mod source;
+pub use source::*;
+
 pub mod taxonomy_db;
 pub use taxonomy_db::*;
+
+pub mod taxonomy_db_set;
+pub use taxonomy_db_set::*;
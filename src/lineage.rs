@@ -0,0 +1,114 @@
+use std::fmt;
+
+use crate::rank::Rank;
+use crate::taxonomy_db::Taxon;
+
+/// A taxon and its ancestors, as found by [`crate::TaxonomyDatabase::query_taxon`]. The root
+/// isn't included.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Lineage {
+    /// From the taxon upwards.
+    taxa: Vec<Taxon>,
+}
+
+impl Lineage {
+    /// A lineage made of `taxa`, given from the taxon upwards.
+    pub fn new(taxa: Vec<Taxon>) -> Self {
+        Lineage { taxa }
+    }
+
+    /// The taxon whose lineage this is.
+    pub fn leaf(&self) -> Option<&Taxon> {
+        self.taxa.first()
+    }
+
+    /// The closest taxon of the given rank, starting from the taxon itself and working
+    /// upwards.
+    pub fn at_rank(&self, rank: Rank) -> Option<&Taxon> {
+        self.taxa.iter().find(|taxon| taxon.rank == rank)
+    }
+
+    pub fn superkingdom(&self) -> Option<&Taxon> {
+        self.at_rank(Rank::Superkingdom)
+    }
+
+    pub fn kingdom(&self) -> Option<&Taxon> {
+        self.at_rank(Rank::Kingdom)
+    }
+
+    pub fn phylum(&self) -> Option<&Taxon> {
+        self.at_rank(Rank::Phylum)
+    }
+
+    pub fn class(&self) -> Option<&Taxon> {
+        self.at_rank(Rank::Class)
+    }
+
+    pub fn order(&self) -> Option<&Taxon> {
+        self.at_rank(Rank::Order)
+    }
+
+    pub fn family(&self) -> Option<&Taxon> {
+        self.at_rank(Rank::Family)
+    }
+
+    pub fn genus(&self) -> Option<&Taxon> {
+        self.at_rank(Rank::Genus)
+    }
+
+    pub fn species(&self) -> Option<&Taxon> {
+        self.at_rank(Rank::Species)
+    }
+
+    /// The taxa from the taxon upwards.
+    pub fn iter(&self) -> std::slice::Iter<'_, Taxon> {
+        self.taxa.iter()
+    }
+
+    /// The taxa from the top down to the taxon.
+    pub fn iter_from_root(&self) -> std::iter::Rev<std::slice::Iter<'_, Taxon>> {
+        self.taxa.iter().rev()
+    }
+
+    pub fn len(&self) -> usize {
+        self.taxa.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.taxa.is_empty()
+    }
+}
+
+impl<'a> IntoIterator for &'a Lineage {
+    type Item = &'a Taxon;
+    type IntoIter = std::slice::Iter<'a, Taxon>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl IntoIterator for Lineage {
+    type Item = Taxon;
+    type IntoIter = std::vec::IntoIter<Taxon>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.taxa.into_iter()
+    }
+}
+
+/// The names of the taxa from the top down, e.g. "Bacteria; Pseudomonadota; ...; Escherichia
+/// coli".
+impl fmt::Display for Lineage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, taxon) in self.iter_from_root().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            f.write_str(&taxon.name)?;
+        }
+        Ok(())
+    }
+}
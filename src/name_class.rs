@@ -0,0 +1,104 @@
+use std::str::FromStr;
+
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+/// The kinds of names that names.dmp attaches to a taxon.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, IntoPrimitive, TryFromPrimitive)]
+#[repr(u8)]
+pub enum NameClass {
+    ScientificName,
+    Synonym,
+    EquivalentName,
+    Includes,
+    InPart,
+    Authority,
+    TypeMaterial,
+
+    CommonName,
+    GenbankCommonName,
+    BlastName,
+    Acronym,
+    GenbankAcronym,
+
+    Misspelling,
+    Misnomer,
+    UnpublishedName,
+
+    Anamorph,
+    Teleomorph,
+    GenbankAnamorph,
+    GenbankSynonym,
+}
+
+impl FromStr for NameClass {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<NameClass, Self::Err> {
+        match input {
+            "scientific name" => Ok(NameClass::ScientificName),
+            "synonym" => Ok(NameClass::Synonym),
+            "equivalent name" => Ok(NameClass::EquivalentName),
+            "includes" => Ok(NameClass::Includes),
+            "in-part" => Ok(NameClass::InPart),
+            "authority" => Ok(NameClass::Authority),
+            "type material" => Ok(NameClass::TypeMaterial),
+            "common name" => Ok(NameClass::CommonName),
+            "genbank common name" => Ok(NameClass::GenbankCommonName),
+            "blast name" => Ok(NameClass::BlastName),
+            "acronym" => Ok(NameClass::Acronym),
+            "genbank acronym" => Ok(NameClass::GenbankAcronym),
+            "misspelling" => Ok(NameClass::Misspelling),
+            "misnomer" => Ok(NameClass::Misnomer),
+            "unpublished name" => Ok(NameClass::UnpublishedName),
+            "anamorph" => Ok(NameClass::Anamorph),
+            "teleomorph" => Ok(NameClass::Teleomorph),
+            "genbank anamorph" => Ok(NameClass::GenbankAnamorph),
+            "genbank synonym" => Ok(NameClass::GenbankSynonym),
+            _ => Err(()),
+        }
+    }
+}
+
+impl From<NameClass> for &'static str {
+    fn from(class: NameClass) -> Self {
+        match class {
+            NameClass::ScientificName => "scientific name",
+            NameClass::Synonym => "synonym",
+            NameClass::EquivalentName => "equivalent name",
+            NameClass::Includes => "includes",
+            NameClass::InPart => "in-part",
+            NameClass::Authority => "authority",
+            NameClass::TypeMaterial => "type material",
+            NameClass::CommonName => "common name",
+            NameClass::GenbankCommonName => "genbank common name",
+            NameClass::BlastName => "blast name",
+            NameClass::Acronym => "acronym",
+            NameClass::GenbankAcronym => "genbank acronym",
+            NameClass::Misspelling => "misspelling",
+            NameClass::Misnomer => "misnomer",
+            NameClass::UnpublishedName => "unpublished name",
+            NameClass::Anamorph => "anamorph",
+            NameClass::Teleomorph => "teleomorph",
+            NameClass::GenbankAnamorph => "genbank anamorph",
+            NameClass::GenbankSynonym => "genbank synonym",
+        }
+    }
+}
+
+/// Name classes are serialized as names.dmp writes them, e.g. "scientific name".
+#[cfg(feature = "serde")]
+impl serde::Serialize for NameClass {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str((*self).into())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for NameClass {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let class = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+        class
+            .parse::<NameClass>()
+            .map_err(|_| serde::de::Error::custom(format!("invalid name class: {:?}", class)))
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use itertools::Itertools;
+use tar::{Archive, Entry};
+
+use crate::error::{errors_first, TaxonomyError, TaxonomyResult};
+use crate::flatfile::read_flatfile_dir;
+use crate::name_class::NameClass;
+use crate::rank::Rank;
+use crate::source::{open_gzipped, SourceStream, TaxonomySource};
+use crate::taxonomy_db::split_accession_version;
+
+fn read_names_file<R: Read>(
+    f: R,
+    file: &Path,
+) -> TaxonomyResult<BTreeMap<u32, Vec<(NameClass, String)>>> {
+    let mut result: BTreeMap<u32, Vec<(NameClass, String)>> = BTreeMap::new();
+    for (i, l) in BufReader::new(f).lines().enumerate() {
+        let line = l?;
+        let fields = line.split('\t').collect::<Vec<&str>>();
+        if fields.len() != 8 {
+            return Err(TaxonomyError::parse(file, i + 1, "Invalid line"));
+        }
+        let taxon = fields[0]
+            .parse::<u32>()
+            .map_err(|_| TaxonomyError::parse(file, i + 1, "Invalid taxon ID"))?;
+        let class = fields[6].parse::<NameClass>().map_err(|_| {
+            TaxonomyError::parse(file, i + 1, format!("Invalid name class: {:?}", fields[6]))
+        })?;
+        result
+            .entry(taxon)
+            .or_default()
+            .push((class, String::from(fields[2])));
+    }
+    Ok(result)
+}
+
+fn read_nodes_file<R: Read>(f: R, file: &Path) -> TaxonomyResult<BTreeMap<u32, (u32, Rank)>> {
+    let mut result = BTreeMap::new();
+    for (i, l) in BufReader::new(f).lines().enumerate() {
+        let line = l?;
+        let field_iter = line.split('\t');
+        let fields = field_iter.collect::<Vec<&str>>();
+        if fields.len() != 26 {
+            return Err(TaxonomyError::parse(file, i + 1, "Invalid line"));
+        }
+        let taxon = fields[0]
+            .parse::<u32>()
+            .map_err(|_| TaxonomyError::parse(file, i + 1, "Invalid taxon ID"))?;
+        let parent = fields[2]
+            .parse::<u32>()
+            .map_err(|_| TaxonomyError::parse(file, i + 1, "Invalid parent taxon ID"))?;
+        let rank = fields[4].parse::<Rank>().map_err(|_| {
+            TaxonomyError::parse(file, i + 1, format!("Invalid rank: {:?}", fields[4]))
+        })?;
+        result.insert(taxon, (parent, rank));
+    }
+    Ok(result)
+}
+
+fn read_merged_file<R: Read>(f: R, file: &Path) -> TaxonomyResult<BTreeMap<u32, u32>> {
+    let mut result = BTreeMap::new();
+    for (i, l) in BufReader::new(f).lines().enumerate() {
+        let line = l?;
+        let fields = line.split('\t').collect::<Vec<&str>>();
+        if fields.len() != 4 {
+            return Err(TaxonomyError::parse(file, i + 1, "Invalid line"));
+        }
+        let old_taxon = fields[0]
+            .parse::<u32>()
+            .map_err(|_| TaxonomyError::parse(file, i + 1, "Invalid old taxon ID"))?;
+        let new_taxon = fields[2]
+            .parse::<u32>()
+            .map_err(|_| TaxonomyError::parse(file, i + 1, "Invalid new taxon ID"))?;
+        result.insert(old_taxon, new_taxon);
+    }
+    Ok(result)
+}
+
+fn read_delnodes_file<R: Read>(f: R, file: &Path) -> TaxonomyResult<Vec<u32>> {
+    let mut result = vec![];
+    for (i, l) in BufReader::new(f).lines().enumerate() {
+        let line = l?;
+        let fields = line.split('\t').collect::<Vec<&str>>();
+        if fields.len() != 2 {
+            return Err(TaxonomyError::parse(file, i + 1, "Invalid line"));
+        }
+        let taxon = fields[0]
+            .parse::<u32>()
+            .map_err(|_| TaxonomyError::parse(file, i + 1, "Invalid taxon ID"))?;
+        result.push(taxon);
+    }
+    Ok(result)
+}
+
+/// Read accession2taxid files, producing (bare accession, version, taxid) triples sorted by
+/// accession. Errors sort first, so that they come out as soon as they're hit.
+fn read_accessions<R: Read>(
+    fs: impl Iterator<Item = (PathBuf, R)>,
+) -> TaxonomyResult<impl Iterator<Item = TaxonomyResult<(String, Option<u32>, u32)>>> {
+    let mut pair_iters = vec![];
+    for (path, f) in fs {
+        let mut lines = BufReader::new(f).lines();
+        let first_line = match lines.next() {
+            Some(l) => l?,
+            None => return Err(TaxonomyError::parse(path, 1, "Empty accession2taxid file")),
+        };
+        let headers = first_line.split('\t').collect::<Vec<&str>>();
+        // Prefer the versioned column, so that we can keep track of versions.
+        let accession_column = headers
+            .iter()
+            .position(|&s| s == "accession.version")
+            .or_else(|| headers.iter().position(|&s| s == "accession"))
+            .ok_or_else(|| TaxonomyError::parse(&path, 1, "Missing accession column"))?;
+        let taxid_column = headers
+            .iter()
+            .position(|&s| s == "taxid")
+            .ok_or_else(|| TaxonomyError::parse(&path, 1, "Missing taxid column"))?;
+        let pair_iter = lines.enumerate().map(move |(i, l)| {
+            // The header was line 1.
+            let line_number = i + 2;
+            let line = l?;
+            let fields = line.split('\t').collect::<Vec<&str>>();
+            let (accession, taxid) = match (fields.get(accession_column), fields.get(taxid_column))
+            {
+                (Some(&accession), Some(&taxid)) => (accession, taxid),
+                _ => return Err(TaxonomyError::parse(&path, line_number, "Invalid line")),
+            };
+            let taxid = taxid
+                .parse::<u32>()
+                .map_err(|_| TaxonomyError::parse(&path, line_number, "Invalid taxon ID"))?;
+            let (bare_acc, version) = split_accession_version(accession);
+            Ok((bare_acc.to_string(), version, taxid))
+        });
+        pair_iters.push(pair_iter)
+    }
+
+    Ok(pair_iters.into_iter().kmerge_by(errors_first(|p, q| p < q)))
+}
+
+fn open_all_gzipped(paths: Vec<PathBuf>) -> TaxonomyResult<Vec<(PathBuf, GzDecoder<File>)>> {
+    paths
+        .into_iter()
+        .map(|path| {
+            let f = open_gzipped(&path)?;
+            Ok((path, f))
+        })
+        .collect()
+}
+
+/// NCBI's taxonomy, as downloaded from ftp.ncbi.nih.gov/pub/taxonomy/: a directory holding
+/// `taxdump.tar.gz`, and an `accession2taxid/` directory of gzipped accession2taxid files.
+pub struct NcbiSource {
+    taxonomy_dir: PathBuf,
+    flatfiles: Option<PathBuf>,
+}
+
+impl NcbiSource {
+    pub fn new(taxonomy_dir: PathBuf) -> Self {
+        NcbiSource {
+            taxonomy_dir,
+            flatfiles: None,
+        }
+    }
+
+    /// Take live accessions from the gzipped GenBank or EMBL flatfiles found anywhere under
+    /// `flatfiles`, rather than from accession2taxid files. The `accession2taxid/` directory is
+    /// then optional, and only supplies `dead_*` files.
+    pub fn flatfiles(mut self, flatfiles: PathBuf) -> Self {
+        self.flatfiles = Some(flatfiles);
+        self
+    }
+
+    /// Read one file out of taxdump.tar.gz, or produce nothing if the archive doesn't have it.
+    /// The archive is reopened each time, rather than holding every file in memory at once.
+    fn read_taxdump_file<T: Default>(
+        &self,
+        name: &str,
+        read: impl FnOnce(Entry<GzDecoder<File>>, &Path) -> TaxonomyResult<T>,
+    ) -> TaxonomyResult<T> {
+        let taxdump_file = File::open(self.taxonomy_dir.join("taxdump.tar.gz"))?;
+        let mut taxdump_archive = Archive::new(GzDecoder::new(taxdump_file));
+        for e in taxdump_archive.entries()? {
+            let entry = e?;
+            let entry_path = entry.path()?.into_owned();
+            if entry_path == Path::new(name) {
+                return read(entry, &entry_path);
+            }
+        }
+        Ok(T::default())
+    }
+
+    /// The accession2taxid files, split into (dead, live). NCBI's dead_*.accession2taxid.gz
+    /// files list accessions that have been suppressed or withdrawn.
+    fn accession2taxid_paths(&self) -> TaxonomyResult<(Vec<PathBuf>, Vec<PathBuf>)> {
+        // Flatfiles stand in for the live accession2taxid files, so the directory may be absent.
+        let taxa_map_dir = match std::fs::read_dir(self.taxonomy_dir.join("accession2taxid")) {
+            Err(e) if self.flatfiles.is_some() && e.kind() == io::ErrorKind::NotFound => vec![],
+            taxa_map_dir => taxa_map_dir?.collect(),
+        };
+        Ok(taxa_map_dir
+            .into_iter()
+            .filter_map(|f| {
+                let path = f.ok()?.path();
+                let path_str = path.to_str().unwrap();
+                if path_str.ends_with("accession2taxid.gz")
+                    || path_str.ends_with("accession2taxid.FULL.gz")
+                    || path_str.ends_with("accession2taxid.EXTRA.gz")
+                {
+                    Some(path)
+                } else {
+                    None
+                }
+            })
+            .partition(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with("dead_"))
+            }))
+    }
+}
+
+impl TaxonomySource for NcbiSource {
+    fn nodes(&self) -> TaxonomyResult<SourceStream<'_, (u32, u32, Rank)>> {
+        let nodes = self.read_taxdump_file("nodes.dmp", |f, path| read_nodes_file(f, path))?;
+        Ok(Box::new(
+            nodes
+                .into_iter()
+                .map(|(taxon, (parent, rank))| Ok((taxon, parent, rank))),
+        ))
+    }
+
+    fn names(&self) -> TaxonomyResult<SourceStream<'_, (u32, NameClass, String)>> {
+        let names = self.read_taxdump_file("names.dmp", |f, path| read_names_file(f, path))?;
+        Ok(Box::new(names.into_iter().flat_map(|(taxon, names)| {
+            names
+                .into_iter()
+                .map(move |(class, name)| Ok((taxon, class, name)))
+        })))
+    }
+
+    fn accessions(&self) -> TaxonomyResult<SourceStream<'_, (String, Option<u32>, u32)>> {
+        Ok(match &self.flatfiles {
+            Some(flatfiles) => Box::new(read_flatfile_dir(flatfiles)?),
+            None => {
+                let (_, live_paths) = self.accession2taxid_paths()?;
+                Box::new(read_accessions(open_all_gzipped(live_paths)?.into_iter())?)
+            }
+        })
+    }
+
+    fn dead_accessions(&self) -> TaxonomyResult<SourceStream<'_, (String, u32)>> {
+        let (dead_paths, _) = self.accession2taxid_paths()?;
+        Ok(Box::new(
+            read_accessions(open_all_gzipped(dead_paths)?.into_iter())?
+                .map(|record| record.map(|(an, _version, taxid)| (an, taxid))),
+        ))
+    }
+
+    fn merged(&self) -> TaxonomyResult<SourceStream<'_, (u32, u32)>> {
+        let merged = self.read_taxdump_file("merged.dmp", |f, path| read_merged_file(f, path))?;
+        Ok(Box::new(merged.into_iter().map(Ok)))
+    }
+
+    fn deleted(&self) -> TaxonomyResult<SourceStream<'_, u32>> {
+        let deleted =
+            self.read_taxdump_file("delnodes.dmp", |f, path| read_delnodes_file(f, path))?;
+        Ok(Box::new(deleted.into_iter().map(Ok)))
+    }
+}
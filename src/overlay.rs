@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use crate::error::{TaxonomyError, TaxonomyResult};
+use crate::rank::Rank;
+use crate::taxonomy_db::split_accession_version;
+
+/// A taxon that only exists in an [`Overlay`].
+#[derive(Clone, Debug)]
+struct OverlayTaxon {
+    parent: u32,
+    rank: Rank,
+    name: String,
+}
+
+/// An accession assigned to a taxon by an [`Overlay`].
+#[derive(Clone, Debug)]
+struct OverlayAccession {
+    taxon: u32,
+    /// The line of the overlay's file that assigned it, if it was read from one.
+    line: Option<usize>,
+}
+
+/// Local taxa and accessions, layered on top of a built [`crate::TaxonomyDatabase`] without
+/// rebuilding it; see [`crate::TaxonomyDatabase::set_overlay`]. Overlay entries take precedence
+/// over the database's own, and answers that depend on them are marked as local.
+///
+/// Overlay taxa can hang off taxa in the database, or off each other, and are seen by lineage
+/// lookups, but not by the tree queries like [`crate::TaxonomyDatabase::children`] that rely on
+/// the database's precomputed indexes.
+#[derive(Clone, Debug, Default)]
+pub struct Overlay {
+    taxa: HashMap<u32, OverlayTaxon>,
+    accessions: HashMap<String, OverlayAccession>,
+    /// The file the overlay was read from, if any.
+    file: Option<PathBuf>,
+}
+
+impl Overlay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read an overlay from a tab-separated file. Each line is either
+    /// `taxon <taxid> <parent taxid> <rank> <name>`, or `accession <accession> <taxid>`, with
+    /// ranks read by [`Rank::parse_lenient`]. Blank lines, and lines starting with '#', are
+    /// ignored. Taxa whose parents lead back round to them are reported, as in
+    /// [`Overlay::add_taxon`]. Accessions are remembered with their line numbers, so that
+    /// [`crate::TaxonomyDatabase::set_overlay`] can point at the line of one whose taxon it
+    /// doesn't know.
+    pub fn read_tsv<R: Read>(f: R, file: &Path) -> TaxonomyResult<Self> {
+        let mut overlay = Overlay {
+            file: Some(file.to_owned()),
+            ..Overlay::new()
+        };
+        for (i, l) in BufReader::new(f).lines().enumerate() {
+            let line = l?;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parse_taxid = |field: &str| {
+                field
+                    .parse::<u32>()
+                    .map_err(|_| TaxonomyError::parse(file, i + 1, "Invalid taxon ID"))
+            };
+            match line.split('\t').collect::<Vec<&str>>()[..] {
+                ["taxon", taxon, parent, rank, name] => {
+                    let rank = Rank::parse_lenient(rank).ok_or_else(|| {
+                        TaxonomyError::parse(file, i + 1, format!("Invalid rank: {:?}", rank))
+                    })?;
+                    overlay
+                        .add_taxon(parse_taxid(taxon)?, parse_taxid(parent)?, rank, name)
+                        .map_err(|e| TaxonomyError::parse(file, i + 1, e.to_string()))?;
+                }
+                ["accession", accession, taxon] => {
+                    overlay.insert_accession(accession, parse_taxid(taxon)?, Some(i + 1));
+                }
+                _ => return Err(TaxonomyError::parse(file, i + 1, "Invalid line")),
+            }
+        }
+        Ok(overlay)
+    }
+
+    /// Like [`Overlay::read_tsv`], for the file at `path`.
+    pub fn from_tsv_file<P: AsRef<Path>>(path: P) -> TaxonomyResult<Self> {
+        let path = path.as_ref();
+        Overlay::read_tsv(File::open(path)?, path)
+    }
+
+    /// Add a taxon, or replace the database's own record of it. This fails if following parents
+    /// through the overlay leads back round to `taxon`. Parents that aren't in the overlay are
+    /// checked against the database by [`crate::TaxonomyDatabase::set_overlay`].
+    pub fn add_taxon(
+        &mut self,
+        taxon: u32,
+        parent: u32,
+        rank: Rank,
+        name: &str,
+    ) -> TaxonomyResult<()> {
+        // The overlay's taxa never loop among themselves, so this walk ends.
+        let mut ancestor = parent;
+        while ancestor != 1 {
+            if ancestor == taxon {
+                return Err(TaxonomyError::OverlayCycle(taxon));
+            }
+            ancestor = match self.parent(ancestor) {
+                Some(parent) => parent,
+                None => break,
+            };
+        }
+        self.taxa.insert(
+            taxon,
+            OverlayTaxon {
+                parent,
+                rank,
+                name: name.to_string(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Assign an accession to a taxon. As in the database, the accession's version is ignored.
+    /// The taxon must be in the overlay or the database by the time the overlay is set.
+    pub fn add_accession(&mut self, accession: &str, taxon: u32) {
+        self.insert_accession(accession, taxon, None);
+    }
+
+    fn insert_accession(&mut self, accession: &str, taxon: u32, line: Option<usize>) {
+        let (bare_acc, _) = split_accession_version(accession);
+        self.accessions
+            .insert(bare_acc.to_string(), OverlayAccession { taxon, line });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.taxa.is_empty() && self.accessions.is_empty()
+    }
+
+    pub fn contains_taxon(&self, taxon: u32) -> bool {
+        self.taxa.contains_key(&taxon)
+    }
+
+    /// Every taxon in the overlay, in no particular order.
+    pub(crate) fn taxa(&self) -> impl Iterator<Item = u32> + '_ {
+        self.taxa.keys().copied()
+    }
+
+    /// Every accession in the overlay, as (bare accession, taxon, line), in no particular order.
+    /// The line is where the overlay's file assigned it, if it was read from one.
+    pub(crate) fn accessions(&self) -> impl Iterator<Item = (&str, u32, Option<usize>)> + '_ {
+        self.accessions
+            .iter()
+            .map(|(acc, a)| (acc.as_str(), a.taxon, a.line))
+    }
+
+    /// The file the overlay was read from, if any.
+    pub(crate) fn file(&self) -> Option<&Path> {
+        self.file.as_deref()
+    }
+
+    pub(crate) fn parent(&self, taxon: u32) -> Option<u32> {
+        self.taxa.get(&taxon).map(|t| t.parent)
+    }
+
+    pub(crate) fn rank(&self, taxon: u32) -> Option<Rank> {
+        self.taxa.get(&taxon).map(|t| t.rank)
+    }
+
+    pub(crate) fn name(&self, taxon: u32) -> Option<&str> {
+        self.taxa.get(&taxon).map(|t| t.name.as_str())
+    }
+
+    pub(crate) fn accession_taxon(&self, bare_acc: &str) -> Option<u32> {
+        self.accessions.get(bare_acc).map(|a| a.taxon)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(text: &str) -> TaxonomyResult<Overlay> {
+        Overlay::read_tsv(text.as_bytes(), Path::new("overlay.tsv"))
+    }
+
+    fn error_line(text: &str) -> Option<usize> {
+        match read(text) {
+            Err(TaxonomyError::Source { line, .. }) => Some(line),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn taxa_and_accessions_are_read() {
+        let overlay = read(
+            "# local\n\
+             taxon\t900000001\t561\tspecies\tEscherichia sp. LAB1\n\
+             \n\
+             accession\tPRIV0001.1\t900000001\n",
+        )
+        .unwrap();
+        assert!(overlay.contains_taxon(900000001));
+        assert_eq!(overlay.parent(900000001), Some(561));
+        assert_eq!(overlay.rank(900000001), Some(Rank::Species));
+        assert_eq!(overlay.name(900000001), Some("Escherichia sp. LAB1"));
+        assert_eq!(overlay.accession_taxon("PRIV0001"), Some(900000001));
+    }
+
+    #[test]
+    fn bad_lines_are_reported() {
+        assert_eq!(error_line("taxon\t9000\t561\tspecies\n"), Some(1));
+        assert_eq!(error_line("taxon\tx\t561\tspecies\tA\n"), Some(1));
+        assert_eq!(error_line("\ntaxon\t9000\t561\tnot a rank\tA\n"), Some(2));
+        assert_eq!(error_line("accession\tPRIV0001\t-1\n"), Some(1));
+        assert_eq!(error_line("something\telse\n"), Some(1));
+    }
+
+    #[test]
+    fn cycles_are_reported() {
+        assert_eq!(error_line("taxon\t9000\t9000\tspecies\tA\n"), Some(1));
+        assert_eq!(
+            error_line(
+                "taxon\t9000\t9001\tspecies\tA\n\
+                 taxon\t9001\t9002\tgenus\tB\n\
+                 taxon\t9002\t9000\tfamily\tC\n"
+            ),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn replacing_a_taxon_cannot_make_a_cycle() {
+        let mut overlay = Overlay::new();
+        overlay.add_taxon(9000, 561, Rank::Genus, "A").unwrap();
+        overlay.add_taxon(9001, 9000, Rank::Species, "B").unwrap();
+        assert!(matches!(
+            overlay.add_taxon(9000, 9001, Rank::Genus, "A"),
+            Err(TaxonomyError::OverlayCycle(9000))
+        ));
+        assert_eq!(overlay.parent(9000), Some(561));
+        overlay.add_taxon(9000, 543, Rank::Genus, "A").unwrap();
+        assert_eq!(overlay.parent(9000), Some(543));
+    }
+}
@@ -1,9 +1,11 @@
+use std::cmp::Ordering;
+use std::fmt;
 use std::str::FromStr;
 
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 
 /// Geez NCBI, make up your mind.
-#[derive(Copy, Clone, Debug, IntoPrimitive, TryFromPrimitive)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, IntoPrimitive, TryFromPrimitive)]
 #[repr(u8)]
 pub enum Rank {
     NoRank,
@@ -64,57 +66,158 @@ pub enum Rank {
     Isolate,
 }
 
+impl Rank {
+    /// Every rank, from the unranked ones down to the most specific.
+    pub fn all() -> impl Iterator<Item = Rank> {
+        (0..).map_while(|n| Rank::try_from(n).ok())
+    }
+
+    /// How far down the hierarchy the rank lies: 0 for superkingdom, and higher for each rank
+    /// below it. `NoRank` and `Clade` can appear anywhere in the tree, so have no level. Nor do
+    /// the infraspecific ranks other than subspecies, varietas and forma, like strain or
+    /// serotype, which aren't nested in one another; a strain can sit below a serotype or
+    /// above one.
+    pub fn level(self) -> Option<u8> {
+        match self {
+            Rank::NoRank
+            | Rank::Clade
+            | Rank::Morph
+            | Rank::FormaSpecialis
+            | Rank::Pathogroup
+            | Rank::Strain
+            | Rank::Serogroup
+            | Rank::Serotype
+            | Rank::Genotype
+            | Rank::Biotype
+            | Rank::Isolate => None,
+            _ => Some(u8::from(self) - u8::from(Rank::Superkingdom)),
+        }
+    }
+
+    /// Whether this is one of the principal Linnaean ranks: superkingdom (domain), kingdom,
+    /// phylum, class, order, family, genus or species.
+    pub fn is_major(self) -> bool {
+        matches!(
+            self,
+            Rank::Superkingdom
+                | Rank::Kingdom
+                | Rank::Phylum
+                | Rank::Class
+                | Rank::Order
+                | Rank::Family
+                | Rank::Genus
+                | Rank::Species
+        )
+    }
+
+    /// The rank of an abbreviation like "subsp." or "f. sp.", as used in names.
+    fn from_abbreviation(input: &str) -> Option<Rank> {
+        match input {
+            "sp." => Some(Rank::Species),
+            "subsp." | "ssp." => Some(Rank::Subspecies),
+            "var." => Some(Rank::Varietas),
+            "f." | "fo." => Some(Rank::Forma),
+            "f. sp." | "f.sp." => Some(Rank::FormaSpecialis),
+            "subg." | "subgen." => Some(Rank::Subgenus),
+            "sect." => Some(Rank::Section),
+            "subsect." => Some(Rank::Subsection),
+            "ser." => Some(Rank::Series),
+            "str." => Some(Rank::Strain),
+            _ => None,
+        }
+    }
+
+    /// Parse a rank the way people write it: as NCBI does, e.g. "species group", but ignoring
+    /// case and extra whitespace, or as a common abbreviation like "subsp.". Use
+    /// [`str::parse`] for NCBI's own files, where anything but NCBI's exact names is an error.
+    pub fn parse_lenient(input: &str) -> Option<Rank> {
+        if let Some(rank) = Rank::from_ncbi_name(input) {
+            return Some(rank);
+        }
+        let normalized = input
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase();
+        Rank::from_ncbi_name(&normalized).or_else(|| Rank::from_abbreviation(&normalized))
+    }
+
+    fn from_ncbi_name(input: &str) -> Option<Rank> {
+        match input {
+            "no rank" => Some(Rank::NoRank),
+            "clade" => Some(Rank::Clade),
+            "superkingdom" => Some(Rank::Superkingdom),
+            "kingdom" => Some(Rank::Kingdom),
+            "subkingdom" => Some(Rank::Subkingdom),
+            "superphylum" => Some(Rank::Superphylum),
+            "phylum" => Some(Rank::Phylum),
+            "subphylum" => Some(Rank::Subphylum),
+            "superclass" => Some(Rank::Superclass),
+            "class" => Some(Rank::Class),
+            "subclass" => Some(Rank::Subclass),
+            "infraclass" => Some(Rank::Infraclass),
+            "cohort" => Some(Rank::Cohort),
+            "subcohort" => Some(Rank::Subcohort),
+            "superorder" => Some(Rank::Superorder),
+            "order" => Some(Rank::Order),
+            "suborder" => Some(Rank::Suborder),
+            "infraorder" => Some(Rank::Infraorder),
+            "parvorder" => Some(Rank::Parvorder),
+            "superfamily" => Some(Rank::Superfamily),
+            "family" => Some(Rank::Family),
+            "subfamily" => Some(Rank::Subfamily),
+            "tribe" => Some(Rank::Tribe),
+            "subtribe" => Some(Rank::Subtribe),
+            "genus" => Some(Rank::Genus),
+            "subgenus" => Some(Rank::Subgenus),
+            "section" => Some(Rank::Section),
+            "subsection" => Some(Rank::Subsection),
+            "series" => Some(Rank::Series),
+            "species group" => Some(Rank::SpeciesGroup),
+            "species subgroup" => Some(Rank::SpeciesSubgroup),
+            "species" => Some(Rank::Species),
+            "subspecies" => Some(Rank::Subspecies),
+            "morph" => Some(Rank::Morph),
+            "varietas" => Some(Rank::Varietas),
+            "forma" => Some(Rank::Forma),
+            "forma specialis" => Some(Rank::FormaSpecialis),
+            "pathogroup" => Some(Rank::Pathogroup),
+            "strain" => Some(Rank::Strain),
+            "serogroup" => Some(Rank::Serogroup),
+            "serotype" => Some(Rank::Serotype),
+            "genotype" => Some(Rank::Genotype),
+            "biotype" => Some(Rank::Biotype),
+            "isolate" => Some(Rank::Isolate),
+            _ => None,
+        }
+    }
+}
+
 impl FromStr for Rank {
     type Err = (); // TODO: when GATs are stablilized, this can be `type Err<'a> = &'a str` so we can hand back the input
 
+    /// Parse a rank exactly as NCBI writes it, e.g. "species group"; see
+    /// [`Rank::parse_lenient`] for anything looser.
     fn from_str(input: &str) -> Result<Rank, Self::Err> {
-        match input {
-            "no rank" => Ok(Rank::NoRank),
-            "clade" => Ok(Rank::Clade),
-            "superkingdom" => Ok(Rank::Superkingdom),
-            "kingdom" => Ok(Rank::Kingdom),
-            "subkingdom" => Ok(Rank::Subkingdom),
-            "superphylum" => Ok(Rank::Superphylum),
-            "phylum" => Ok(Rank::Phylum),
-            "subphylum" => Ok(Rank::Subphylum),
-            "superclass" => Ok(Rank::Superclass),
-            "class" => Ok(Rank::Class),
-            "subclass" => Ok(Rank::Subclass),
-            "infraclass" => Ok(Rank::Infraclass),
-            "cohort" => Ok(Rank::Cohort),
-            "subcohort" => Ok(Rank::Subcohort),
-            "superorder" => Ok(Rank::Superorder),
-            "order" => Ok(Rank::Order),
-            "suborder" => Ok(Rank::Suborder),
-            "infraorder" => Ok(Rank::Infraorder),
-            "parvorder" => Ok(Rank::Parvorder),
-            "superfamily" => Ok(Rank::Superfamily),
-            "family" => Ok(Rank::Family),
-            "subfamily" => Ok(Rank::Subfamily),
-            "tribe" => Ok(Rank::Tribe),
-            "subtribe" => Ok(Rank::Subtribe),
-            "genus" => Ok(Rank::Genus),
-            "subgenus" => Ok(Rank::Subgenus),
-            "section" => Ok(Rank::Section),
-            "subsection" => Ok(Rank::Subsection),
-            "series" => Ok(Rank::Series),
-            "species group" => Ok(Rank::SpeciesGroup),
-            "species subgroup" => Ok(Rank::SpeciesSubgroup),
-            "species" => Ok(Rank::Species),
-            "subspecies" => Ok(Rank::Subspecies),
-            "morph" => Ok(Rank::Morph),
-            "varietas" => Ok(Rank::Varietas),
-            "forma" => Ok(Rank::Forma),
-            "forma specialis" => Ok(Rank::FormaSpecialis),
-            "pathogroup" => Ok(Rank::Pathogroup),
-            "strain" => Ok(Rank::Strain),
-            "serogroup" => Ok(Rank::Serogroup),
-            "serotype" => Ok(Rank::Serotype),
-            "genotype" => Ok(Rank::Genotype),
-            "biotype" => Ok(Rank::Biotype),
-            "isolate" => Ok(Rank::Isolate),
-            _ => Err(()),
+        Rank::from_ncbi_name(input).ok_or(())
+    }
+}
+
+/// Ranks are ordered by how far down the hierarchy they lie, so that `Rank::Genus <
+/// Rank::Species`. Ranks without a [`Rank::level`] aren't ordered against anything but
+/// themselves.
+impl PartialOrd for Rank {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self == other {
+            return Some(Ordering::Equal);
         }
+        self.level()?.partial_cmp(&other.level()?)
+    }
+}
+
+impl fmt::Display for Rank {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str((*self).into())
     }
 }
 
@@ -168,3 +271,154 @@ impl From<Rank> for &'static str {
         }
     }
 }
+
+/// Ranks are serialized as NCBI writes them, e.g. "species group".
+#[cfg(feature = "serde")]
+impl serde::Serialize for Rank {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str((*self).into())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Rank {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let rank = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+        rank.parse::<Rank>()
+            .map_err(|_| serde::de::Error::custom(format!("invalid rank: {:?}", rank)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn all_lists_every_rank_once_in_order() {
+        let ranks = Rank::all().collect::<Vec<_>>();
+        assert_eq!(ranks.first(), Some(&Rank::NoRank));
+        assert_eq!(ranks.last(), Some(&Rank::Isolate));
+        for (n, rank) in ranks.iter().enumerate() {
+            assert_eq!(u8::from(*rank) as usize, n);
+        }
+    }
+
+    #[test]
+    fn names_round_trip_strictly() {
+        for rank in Rank::all() {
+            let name: &str = rank.into();
+            assert_eq!(name.parse::<Rank>(), Ok(rank));
+            assert_eq!(rank.to_string(), name);
+        }
+        assert!("Species".parse::<Rank>().is_err());
+        assert!("species  group".parse::<Rank>().is_err());
+        assert!("subsp.".parse::<Rank>().is_err());
+    }
+
+    #[test]
+    fn lenient_parsing_ignores_case_and_spacing() {
+        assert_eq!(Rank::parse_lenient("species"), Some(Rank::Species));
+        assert_eq!(Rank::parse_lenient("Species"), Some(Rank::Species));
+        assert_eq!(
+            Rank::parse_lenient(" Species\tGroup "),
+            Some(Rank::SpeciesGroup)
+        );
+        assert_eq!(Rank::parse_lenient("speciesgroup"), None);
+    }
+
+    #[test]
+    fn abbreviations_are_parsed_leniently() {
+        assert_eq!(Rank::parse_lenient("sp."), Some(Rank::Species));
+        assert_eq!(Rank::parse_lenient("subsp."), Some(Rank::Subspecies));
+        assert_eq!(Rank::parse_lenient("SSP."), Some(Rank::Subspecies));
+        assert_eq!(Rank::parse_lenient("var."), Some(Rank::Varietas));
+        assert_eq!(Rank::parse_lenient("f."), Some(Rank::Forma));
+        assert_eq!(Rank::parse_lenient("f.  sp."), Some(Rank::FormaSpecialis));
+        assert_eq!(Rank::parse_lenient("f.sp."), Some(Rank::FormaSpecialis));
+        assert_eq!(Rank::parse_lenient("str."), Some(Rank::Strain));
+        assert_eq!(Rank::parse_lenient("sp"), None);
+    }
+
+    #[test]
+    fn levels_follow_the_nested_ranks() {
+        assert_eq!(Rank::Superkingdom.level(), Some(0));
+        assert_eq!(Rank::Kingdom.level(), Some(1));
+        let nested = [
+            Rank::Superkingdom,
+            Rank::Phylum,
+            Rank::Family,
+            Rank::Genus,
+            Rank::Species,
+            Rank::Subspecies,
+            Rank::Varietas,
+            Rank::Forma,
+        ];
+        for pair in nested.windows(2) {
+            assert!(pair[0].level() < pair[1].level(), "{:?}", pair);
+        }
+        for rank in [
+            Rank::NoRank,
+            Rank::Clade,
+            Rank::Morph,
+            Rank::FormaSpecialis,
+            Rank::Pathogroup,
+            Rank::Strain,
+            Rank::Serogroup,
+            Rank::Serotype,
+            Rank::Genotype,
+            Rank::Biotype,
+            Rank::Isolate,
+        ] {
+            assert_eq!(rank.level(), None, "{:?}", rank);
+        }
+    }
+
+    #[test]
+    fn ranks_are_ordered_by_depth() {
+        assert!(Rank::Genus < Rank::Species);
+        assert!(Rank::Superkingdom < Rank::Forma);
+        assert!(Rank::Family > Rank::Order);
+        assert_eq!(
+            Rank::Species.partial_cmp(&Rank::Species),
+            Some(Ordering::Equal)
+        );
+    }
+
+    #[test]
+    fn ranks_without_levels_are_only_equal_to_themselves() {
+        for (p, q) in [
+            (Rank::Clade, Rank::Genus),
+            (Rank::NoRank, Rank::Clade),
+            (Rank::Strain, Rank::Species),
+            (Rank::Strain, Rank::Serotype),
+            (Rank::Isolate, Rank::Forma),
+        ] {
+            assert_eq!(p.partial_cmp(&q), None, "{:?} {:?}", p, q);
+            assert_eq!(q.partial_cmp(&p), None, "{:?} {:?}", q, p);
+        }
+        assert_eq!(
+            Rank::Strain.partial_cmp(&Rank::Strain),
+            Some(Ordering::Equal)
+        );
+    }
+
+    #[test]
+    fn major_ranks_are_the_linnaean_ones() {
+        let major = Rank::all()
+            .filter(|rank| rank.is_major())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            major,
+            vec![
+                Rank::Superkingdom,
+                Rank::Kingdom,
+                Rank::Phylum,
+                Rank::Class,
+                Rank::Order,
+                Rank::Family,
+                Rank::Genus,
+                Rank::Species,
+            ]
+        );
+    }
+}
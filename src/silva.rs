@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use crate::error::{TaxonomyError, TaxonomyResult};
+use crate::name_class::NameClass;
+use crate::rank::Rank;
+use crate::source::{open_maybe_gzipped, SourceStream, TaxonomySource};
+
+/// The parent given to SILVA's domains, which its taxonomy files list without one. SILVA
+/// numbers its taxa from 2, so 1 is free.
+const SILVA_ROOT: u32 = 1;
+
+/// Map a SILVA rank onto a rank. SILVA mostly uses NCBI's rank names, plus a few of its own.
+fn silva_rank(rank: &str) -> Rank {
+    match rank {
+        "domain" => Rank::Superkingdom,
+        "major_clade" => Rank::Clade,
+        _ => rank.parse::<Rank>().unwrap_or(Rank::NoRank),
+    }
+}
+
+/// Read a SILVA taxonomy file like `tax_slv_ssu_138.1.txt`, producing (taxon, parent, rank,
+/// name) for every taxon. Each line holds a taxon's path, like "Bacteria;Proteobacteria;", its
+/// ID and its rank; parents are found by their paths.
+fn read_silva_taxonomy_file<R: Read>(
+    f: R,
+    file: &Path,
+) -> TaxonomyResult<Vec<(u32, u32, Rank, String)>> {
+    let mut taxa = vec![];
+    for (i, l) in BufReader::new(f).lines().enumerate() {
+        let line = l?;
+        let fields = line.split('\t').collect::<Vec<&str>>();
+        if fields.len() < 3 {
+            return Err(TaxonomyError::parse(file, i + 1, "Invalid line"));
+        }
+        let taxon = fields[1]
+            .parse::<u32>()
+            .map_err(|_| TaxonomyError::parse(file, i + 1, "Invalid taxon ID"))?;
+        if taxon == SILVA_ROOT {
+            return Err(TaxonomyError::parse(
+                file,
+                i + 1,
+                "Unexpected root taxon ID",
+            ));
+        }
+        let path = fields[0].trim_end_matches(';');
+        let (parent_path, name) = match path.rsplit_once(';') {
+            Some((parent_path, name)) => (Some(parent_path), name),
+            None => (None, path),
+        };
+        if name.is_empty() {
+            return Err(TaxonomyError::parse(file, i + 1, "Invalid taxon path"));
+        }
+        taxa.push((
+            i + 1,
+            path.to_string(),
+            parent_path.map(String::from),
+            taxon,
+            silva_rank(fields[2]),
+            name.to_string(),
+        ));
+    }
+
+    let taxa_by_path = taxa
+        .iter()
+        .map(|(_, path, _, taxon, _, _)| (path.as_str(), *taxon))
+        .collect::<HashMap<&str, u32>>();
+    let mut result = Vec::with_capacity(taxa.len());
+    for (line, _, parent_path, taxon, rank, name) in &taxa {
+        let parent = match parent_path {
+            Some(parent_path) => *taxa_by_path
+                .get(parent_path.as_str())
+                .ok_or_else(|| TaxonomyError::parse(file, *line, "Unknown parent taxon"))?,
+            None => SILVA_ROOT,
+        };
+        result.push((*taxon, parent, *rank, name.clone()));
+    }
+    Ok(result)
+}
+
+/// Split a SILVA sequence key like "AB000001.1.1500" into its accession and the start and stop
+/// of the sequence within it.
+fn split_silva_key(key: &str) -> Option<(&str, u32, u32)> {
+    let mut parts = key.rsplitn(3, '.');
+    let stop = parts.next()?.parse::<u32>().ok()?;
+    let start = parts.next()?.parse::<u32>().ok()?;
+    Some((parts.next()?, start, stop))
+}
+
+/// Read a SILVA accession map, producing (sequence key, taxon) pairs, with keys like
+/// "AB000001.1.1500". This may either be an `.acc_taxid` file, keyed that way already, or a
+/// `taxmap_slv_*.txt` file, with separate `primaryAccession`, `start`, `stop` and `taxid`
+/// columns.
+fn read_silva_accessions_file<R: Read>(f: R, file: &Path) -> TaxonomyResult<Vec<(String, u32)>> {
+    let mut lines = BufReader::new(f).lines().enumerate().peekable();
+    // taxmap files have a header; .acc_taxid files don't.
+    let columns = match lines.peek() {
+        Some((_, Ok(first_line))) if first_line.starts_with("primaryAccession") => {
+            let headers = first_line.split('\t').collect::<Vec<&str>>();
+            let column = |name: &str| {
+                headers.iter().position(|&s| s == name).ok_or_else(|| {
+                    TaxonomyError::parse(file, 1, format!("Missing {} column", name))
+                })
+            };
+            let columns = [column("start")?, column("stop")?, column("taxid")?];
+            lines.next();
+            Some(columns)
+        }
+        _ => None,
+    };
+
+    let mut result = vec![];
+    for (i, l) in lines {
+        let line = l?;
+        let fields = line.split('\t').collect::<Vec<&str>>();
+        let (key, taxid) = match columns {
+            Some([start_column, stop_column, taxid_column]) => match (
+                fields.first(),
+                fields.get(start_column),
+                fields.get(stop_column),
+                fields.get(taxid_column),
+            ) {
+                (Some(&accession), Some(&start), Some(&stop), Some(&taxid)) => {
+                    (format!("{}.{}.{}", accession, start, stop), taxid)
+                }
+                _ => return Err(TaxonomyError::parse(file, i + 1, "Invalid line")),
+            },
+            None => match fields[..] {
+                [key, taxid] => (key.to_string(), taxid),
+                _ => return Err(TaxonomyError::parse(file, i + 1, "Invalid line")),
+            },
+        };
+        if split_silva_key(&key).is_none() {
+            return Err(TaxonomyError::parse(
+                file,
+                i + 1,
+                format!("Invalid sequence key: {:?}", key),
+            ));
+        }
+        let taxid = taxid
+            .parse::<u32>()
+            .map_err(|_| TaxonomyError::parse(file, i + 1, "Invalid taxon ID"))?;
+        result.push((key, taxid));
+    }
+    Ok(result)
+}
+
+/// The SILVA taxonomy, built from a directory holding one taxonomy file, like
+/// `tax_slv_ssu_138.1.txt`, and any number of accession maps for it, like
+/// `tax_slv_ssu_138.1.acc_taxid` or `taxmap_slv_ssu_ref_nr_138.1.txt`, optionally gzipped.
+/// SILVA's SSU and LSU taxonomies use different IDs, so each needs a directory of its own.
+///
+/// SILVA identifies sequences by "accession.start.stop", and different regions of one accession
+/// can belong to different taxa, so sequences are indexed by the whole key. Queries have to
+/// name a region, like "AB000001.1.1500", and only regions that SILVA lists are answered for.
+pub struct SilvaSource {
+    silva_dir: PathBuf,
+}
+
+impl SilvaSource {
+    pub fn new(silva_dir: PathBuf) -> Self {
+        SilvaSource { silva_dir }
+    }
+
+    /// The taxonomy file, and the accession maps.
+    fn paths(&self) -> TaxonomyResult<(PathBuf, Vec<PathBuf>)> {
+        let mut taxonomy_paths = vec![];
+        let mut accession_paths = vec![];
+        for e in std::fs::read_dir(&self.silva_dir)? {
+            let path = e?.path();
+            let name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name.trim_end_matches(".gz"),
+                None => continue,
+            };
+            if name.ends_with(".acc_taxid") || name.starts_with("taxmap_slv_") {
+                accession_paths.push(path);
+            } else if name.starts_with("tax_slv_") && name.ends_with(".txt") {
+                taxonomy_paths.push(path);
+            }
+        }
+        accession_paths.sort();
+        match &taxonomy_paths[..] {
+            [taxonomy_path] => Ok((taxonomy_path.clone(), accession_paths)),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "expected exactly one tax_slv_*.txt file in {}, found {}",
+                    self.silva_dir.display(),
+                    taxonomy_paths.len()
+                ),
+            )
+            .into()),
+        }
+    }
+
+    fn read_taxonomy(&self) -> TaxonomyResult<Vec<(u32, u32, Rank, String)>> {
+        let (taxonomy_path, _) = self.paths()?;
+        read_silva_taxonomy_file(open_maybe_gzipped(&taxonomy_path)?, &taxonomy_path)
+    }
+}
+
+impl TaxonomySource for SilvaSource {
+    fn nodes(&self) -> TaxonomyResult<SourceStream<'_, (u32, u32, Rank)>> {
+        let taxa = self.read_taxonomy()?;
+        let root = std::iter::once(Ok((SILVA_ROOT, SILVA_ROOT, Rank::NoRank)));
+        Ok(Box::new(root.chain(taxa.into_iter().map(
+            |(taxon, parent, rank, _)| Ok((taxon, parent, rank)),
+        ))))
+    }
+
+    fn names(&self) -> TaxonomyResult<SourceStream<'_, (u32, NameClass, String)>> {
+        let taxa = self.read_taxonomy()?;
+        let root = std::iter::once(Ok((
+            SILVA_ROOT,
+            NameClass::ScientificName,
+            "root".to_string(),
+        )));
+        Ok(Box::new(root.chain(taxa.into_iter().map(
+            |(taxon, _, _, name)| Ok((taxon, NameClass::ScientificName, name)),
+        ))))
+    }
+
+    fn accessions(&self) -> TaxonomyResult<SourceStream<'_, (String, Option<u32>, u32)>> {
+        let (_, accession_paths) = self.paths()?;
+        let mut accessions = vec![];
+        for path in accession_paths {
+            accessions.extend(read_silva_accessions_file(
+                open_maybe_gzipped(&path)?,
+                &path,
+            )?);
+        }
+        // An acc_taxid file and a taxmap file can both list a sequence; `sort_by` leaves them
+        // in file order, and the build keeps the first.
+        accessions.sort_by(|p, q| p.0.cmp(&q.0));
+        Ok(Box::new(
+            accessions
+                .into_iter()
+                .map(|(key, taxid)| Ok((key, None, taxid))),
+        ))
+    }
+
+    /// Sequence keys between two listed ones needn't exist, let alone share their taxon.
+    fn interpolate_accessions(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_accessions(text: &str) -> TaxonomyResult<Vec<(String, u32)>> {
+        read_silva_accessions_file(text.as_bytes(), Path::new("test.txt"))
+    }
+
+    fn pair(key: &str, taxid: u32) -> (String, u32) {
+        (key.to_string(), taxid)
+    }
+
+    #[test]
+    fn acc_taxid_keys_are_kept_whole() {
+        let text = "AB000001.1.1500\t2700\nAB000001.1600.3000\t3\n";
+        assert_eq!(
+            read_accessions(text).unwrap(),
+            vec![pair("AB000001.1.1500", 2700), pair("AB000001.1600.3000", 3)]
+        );
+    }
+
+    #[test]
+    fn taxmap_columns_make_keys() {
+        let text = "\
+primaryAccession\tstart\tstop\tpath\torganism_name\ttaxid
+CP000001\t1\t1500\tArchaea;\tfoo\t2
+CP000001\t2000\t3500\tBacteria;\tbar\t3
+";
+        assert_eq!(
+            read_accessions(text).unwrap(),
+            vec![pair("CP000001.1.1500", 2), pair("CP000001.2000.3500", 3)]
+        );
+    }
+
+    #[test]
+    fn taxmap_needs_its_columns() {
+        let text = "primaryAccession\tstart\tpath\ttaxid\nCP000001\t1\tArchaea;\t2\n";
+        assert!(matches!(
+            read_accessions(text),
+            Err(TaxonomyError::Source { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn bad_accession_lines_are_reported() {
+        for text in [
+            "AB000001.1.1500\t2700\nAB000002\t3\n",
+            "AB000001.1.1500\t2700\nAB000002.1.1500\tx\n",
+            "AB000001.1.1500\t2700\nAB000002.1.1500\n",
+        ] {
+            assert!(matches!(
+                read_accessions(text),
+                Err(TaxonomyError::Source { line: 2, .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn taxonomy_parents_are_found_by_path() {
+        let text = "\
+Bacteria;\t3\tdomain\t\t138.1
+Bacteria;SAR324 clade;\t2800\tmajor_clade\t\t138.1
+Bacteria;SAR324 clade;Foo;\t2900\tgenus\t\t138.1
+";
+        let taxa = read_silva_taxonomy_file(text.as_bytes(), Path::new("tax.txt")).unwrap();
+        assert_eq!(
+            taxa,
+            vec![
+                (3, SILVA_ROOT, Rank::Superkingdom, "Bacteria".to_string()),
+                (2800, 3, Rank::Clade, "SAR324 clade".to_string()),
+                (2900, 2800, Rank::Genus, "Foo".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_taxonomy_parents_are_reported() {
+        let text = "Bacteria;\t3\tdomain\nArchaea;Foo;\t4\tphylum\n";
+        assert!(matches!(
+            read_silva_taxonomy_file(text.as_bytes(), Path::new("tax.txt")),
+            Err(TaxonomyError::Source { line: 2, .. })
+        ));
+    }
+}
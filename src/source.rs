@@ -0,0 +1,66 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+
+use crate::error::TaxonomyResult;
+use crate::name_class::NameClass;
+use crate::rank::Rank;
+
+/// A stream of records from a [`TaxonomySource`].
+pub type SourceStream<'a, T> = Box<dyn Iterator<Item = TaxonomyResult<T>> + 'a>;
+
+/// Open a gzipped file for reading.
+pub(crate) fn open_gzipped(path: &Path) -> TaxonomyResult<GzDecoder<File>> {
+    Ok(GzDecoder::new(File::open(path)?))
+}
+
+/// Open a file for reading, decompressing it if its name ends in ".gz".
+pub(crate) fn open_maybe_gzipped(path: &Path) -> TaxonomyResult<Box<dyn Read>> {
+    Ok(if path.extension().is_some_and(|ext| ext == "gz") {
+        Box::new(open_gzipped(path)?)
+    } else {
+        Box::new(File::open(path)?)
+    })
+}
+
+/// Somewhere to build a [`crate::TaxonomyDatabase`] from, such as NCBI's taxonomy files (see
+/// [`crate::NcbiSource`]). Each method is called once per build, and any error in a stream
+/// aborts the build.
+pub trait TaxonomySource {
+    /// Every taxon, as (taxon, parent, rank). The root is its own parent.
+    fn nodes(&self) -> TaxonomyResult<SourceStream<'_, (u32, u32, Rank)>>;
+
+    /// The names of taxa, as (taxon, class, name). Each taxon should have one scientific name;
+    /// the order of a taxon's names is kept.
+    fn names(&self) -> TaxonomyResult<SourceStream<'_, (u32, NameClass, String)>>;
+
+    /// Live accessions, as (bare accession, version, taxon), sorted by accession. Where an
+    /// accession appears more than once, the first record wins. Ranges of accessions like
+    /// "AAAA01000001-AAAA01099999" may be given as a single record.
+    fn accessions(&self) -> TaxonomyResult<SourceStream<'_, (String, Option<u32>, u32)>>;
+
+    /// Whether an accession falling between two of [`TaxonomySource::accessions`] that share a
+    /// taxon can be assumed to belong to it too. That holds for NCBI's accessions, which are
+    /// handed out in runs, but not for a sparse selection like GTDB's genomes. If not, every
+    /// accession is stored, and lookups only answer for those.
+    fn interpolate_accessions(&self) -> bool {
+        true
+    }
+
+    /// Accessions that have been retired, as (bare accession, last taxon), sorted by accession.
+    fn dead_accessions(&self) -> TaxonomyResult<SourceStream<'_, (String, u32)>> {
+        Ok(Box::new(std::iter::empty()))
+    }
+
+    /// Taxa that have been merged into others, as (old taxon, new taxon).
+    fn merged(&self) -> TaxonomyResult<SourceStream<'_, (u32, u32)>> {
+        Ok(Box::new(std::iter::empty()))
+    }
+
+    /// Taxa that have been deleted.
+    fn deleted(&self) -> TaxonomyResult<SourceStream<'_, u32>> {
+        Ok(Box::new(std::iter::empty()))
+    }
+}
@@ -1,117 +1,124 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
 use std::fs::File;
-use std::io::{self, BufRead, BufReader, Read};
+use std::io;
 use std::path::Path;
 
 use flate2::read::GzDecoder;
-use itertools::Itertools;
+use itertools::{process_results, Itertools};
 use tar::Archive;
 
+use crate::error::{errors_first, TaxonomyError, TaxonomyResult};
+use crate::lineage::Lineage;
+use crate::name_class::NameClass;
+use crate::ncbi::NcbiSource;
+use crate::overlay::Overlay;
 use crate::rank::Rank;
-
-fn data_error(msg: &str) -> io::Error {
-    io::Error::new(io::ErrorKind::InvalidData, msg)
-}
+use crate::source::TaxonomySource;
 
 pub enum TaxonomyDatabaseSource {
     FromExisting,
+    /// Build a new database from any [`TaxonomySource`].
+    FromSource(Box<dyn TaxonomySource>),
     FromGzipped(std::path::PathBuf),
     // FromGzippedUrl(url::Url),
     FromFiles(std::path::PathBuf),
     // FromFilesUrl(url::Url)
+    /// Like `FromFiles`, but take live accessions from the flatfiles under `flatfiles`; see
+    /// [`NcbiSource::flatfiles`].
+    FromFlatfiles {
+        taxonomy: std::path::PathBuf,
+        flatfiles: std::path::PathBuf,
+    },
 }
 
 pub struct TaxonomyDatabaseConfig {
     source: TaxonomyDatabaseSource,
     cache_size: Option<u64>,
     location: Option<std::path::PathBuf>,
+    build_options: BuildOptions,
 }
 
-fn read_names_file<R: Read>(f: R) -> io::Result<BTreeMap<u32, String>> {
-    let mut result = BTreeMap::new();
-    for l in BufReader::new(f).lines() {
-        let line = l?;
-        let fields = line.split('\t').collect::<Vec<&str>>();
-        if fields.len() != 8 {
-            return Err(data_error("Invalid line in names.dmp"));
-        }
-        if fields[6] != "scientific name" {
-            continue;
-        }
-        let taxon = fields[0]
-            .parse::<u32>()
-            .map_err(|_| data_error("Invalid taxon ID in names.dmp"))?;
-        result.insert(taxon, String::from(fields[2]));
-    }
-    Ok(result)
-}
-
-fn read_nodes_file<R: Read>(f: R) -> io::Result<BTreeMap<u32, (u32, Rank)>> {
-    let mut result = BTreeMap::new();
-    for l in BufReader::new(f).lines() {
-        let line = l?;
-        let field_iter = line.split('\t');
-        let fields = field_iter.collect::<Vec<&str>>();
-        if fields.len() != 26 {
-            return Err(data_error("Invalid line in nodes.dmp"));
-        }
-        let taxon = fields[0]
-            .parse::<u32>()
-            .map_err(|_| data_error("Invalid taxon ID in nodes.dmp"))?;
-        let parent = fields[2]
-            .parse::<u32>()
-            .map_err(|_| data_error("Invalid taxon ID in nodes.dmp"))?;
-        // ranks.insert(fields[4].to_owned());
-        let rank = fields[4].parse::<Rank>().map_err(|_| {
-            let msg = format!("Invalid rank in nodes.dmp: {:?}", fields[4]);
-            data_error(&msg)
-        })?;
-        result.insert(taxon, (parent, rank));
-    }
-    Ok(result)
-}
-
-fn read_accessions<R: Read>(
-    fs: impl Iterator<Item = R>,
-) -> io::Result<impl Iterator<Item = (String, u32)>> {
-    let mut pair_iters = vec![];
-    for f in fs {
-        let mut lines = BufReader::new(f).lines();
-        let first_line = lines
-            .next()
-            .unwrap_or_else(|| Err(data_error("Empty accessions2taxid file")))?;
-        let headers = first_line.split('\t').collect::<Vec<&str>>();
-        let accession_column = headers
-            .iter()
-            .position(|&s| s == "accession" || s == "accession.version")
-            .ok_or_else(|| data_error("accessions2taxid file missing accession column"))?;
-        let taxid_column = headers
-            .iter()
-            .position(|&s| s == "taxid")
-            .ok_or_else(|| data_error("accessions2taxid file missing taxid column"))?;
-        let pair_iter = lines.filter_map(move |l| {
-            let line = l.ok()?;
-            let field_iter = line.split('\t');
-            let fields = field_iter.map(|s| s.to_string()).collect::<Vec<String>>();
-            let taxid = fields[taxid_column].parse::<u32>().ok()?;
-            let accession = &fields[accession_column];
-            let bare_acc: String = accession.split('.').next().unwrap().to_string();
-            Some((bare_acc, taxid))
-        });
-        pair_iters.push(pair_iter)
+/// Settings that only matter when building a new database from source files.
+#[derive(Default)]
+struct BuildOptions {
+    exact_membership: bool,
+    accession_versions: bool,
+}
+
+/// Split an accession like "U39076.1" into its bare accession and version. Anything else
+/// after a '.', like the start and stop of a SILVA sequence key "AB000001.1.1500", is kept as
+/// part of the accession.
+pub fn split_accession_version(accession: &str) -> (&str, Option<u32>) {
+    match accession.split_once('.') {
+        Some((bare_acc, version)) => match version.parse::<u32>() {
+            Ok(version) => (bare_acc, Some(version)),
+            Err(_) => (accession, None),
+        },
+        None => (accession, None),
     }
+}
 
-    use itertools::kmerge;
-    Ok(kmerge(pair_iters))
+/// Split a range of accessions like "AAAA01000001-AAAA01099999", as found in GenBank
+/// `ACCESSION` lines and WGS projects, into its first and last accessions. Both ends must share
+/// the same alphabetic prefix and have the same length, so that every accession between them
+/// sorts between them too.
+fn split_accession_range(accession: &str) -> Option<(&str, &str)> {
+    let (start, end) = accession.split_once('-')?;
+    let prefix = |an: &str| an.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    if start.is_empty() || start.len() != end.len() || start > end || prefix(start) != prefix(end) {
+        return None;
+    }
+    if start[..prefix(start)] != end[..prefix(end)] {
+        return None;
+    }
+    Some((start, end))
 }
 
+/// Accession ranges like "AAAA01000001-AAAA01099999" are stored whole in `ranges`. If
+/// `membership` is given, every other accession is also recorded there, so that lookups can
+/// tell real accessions from ones that merely fall between two endstops. If `versions` is
+/// given, the taxon of every version of every accession is recorded there.
 fn read_accessions_to_db(
-    pairs: impl Iterator<Item = (String, u32)>,
+    records: impl Iterator<Item = (String, Option<u32>, u32)>,
     db: &sled::Tree,
-) -> io::Result<()> {
+    ranges: &sled::Tree,
+    membership: Option<&sled::Tree>,
+    versions: Option<&sled::Tree>,
+    interpolate: bool,
+) -> TaxonomyResult<()> {
+    // Ranges and versions have to be recorded before duplicate accessions are dropped below.
+    // Since that happens inside an iterator adapter, any error is stashed until the end.
+    let mut side_result = Ok(());
+    let records = records
+        .inspect(|(an, version, taxid)| {
+            if side_result.is_err() {
+                return;
+            }
+            if let Some((start, end)) = split_accession_range(an) {
+                side_result = insert_accession_range(ranges, start, end, *taxid);
+            } else if let (Some(versions), Some(version)) = (versions, version) {
+                side_result = insert_accession_version(versions, an, *version, *taxid);
+            }
+        })
+        // Anything else with a '-' in it is a malformed range, and has no place among the
+        // single accessions either.
+        .filter(|(an, _, _)| !an.contains('-'));
+    let pairs = records.map(|(an, _version, taxid)| (an, taxid));
+
     // First, we want to totally ignore any duplicated accessions. If we have already seen
     // an accession, we ignore all subsequent ones.
-    for (_, mut pair_group) in &pairs.dedup_by(|p, q| p.0 == q.0).group_by(|p| p.1) {
+    let pairs = pairs.dedup_by(|p, q| p.0 == q.0);
+    if !interpolate {
+        // Nothing can be assumed about the accessions between these, so every one is stored.
+        for (an, taxid) in pairs {
+            if let Some(membership) = membership {
+                membership.insert(an.as_str(), &[])?;
+            }
+            db.insert(an, &taxid.to_le_bytes())?;
+        }
+        return side_result;
+    }
+    for (_, mut pair_group) in &pairs.group_by(|p| p.1) {
         // Having dealt with that possibility, we want to insert the "endstops" of every run of
         // shared taxids.
         let (an_start, taxid_start) = pair_group
@@ -119,95 +126,382 @@ fn read_accessions_to_db(
             .expect("group_by() should always produce at least one element per group");
         let taxid_bytes = taxid_start.to_le_bytes();
         db.insert(an_start.clone(), &taxid_bytes)?;
-        if let Some((an_end, _taxid_end)) = pair_group.last() {
-            db.insert(an_end.clone(), &taxid_bytes)?;
+        let an_end = match membership {
+            Some(membership) => {
+                membership.insert(an_start.as_str(), &[])?;
+                let mut an_end = None;
+                for (an, _) in pair_group {
+                    membership.insert(an.as_str(), &[])?;
+                    an_end = Some(an);
+                }
+                an_end
+            }
+            None => pair_group.last().map(|(an, _)| an),
+        };
+        if let Some(an_end) = an_end {
+            db.insert(an_end, &taxid_bytes)?;
         }
     }
+    side_result
+}
+
+/// Entries in `ACCESSION_RANGES` are keyed by the first accession of the range. The value is
+/// the taxid, followed by the last accession of the range.
+fn range_value(end: &str, taxid: u32) -> Vec<u8> {
+    let mut value = taxid.to_le_bytes().to_vec();
+    value.extend_from_slice(end.as_bytes());
+    value
+}
+
+fn parse_range_value(value: &[u8]) -> Option<(u32, &[u8])> {
+    if value.len() < 4 {
+        return None;
+    }
+    let (taxid, end) = value.split_at(4);
+    Some((parse_taxid(taxid)?, end))
+}
+
+fn insert_accession_range(
+    ranges: &sled::Tree,
+    start: &str,
+    end: &str,
+    taxid: u32,
+) -> TaxonomyResult<()> {
+    // As with single accessions, the first record of a range wins.
+    if !ranges.contains_key(start)? {
+        ranges.insert(start, range_value(end, taxid))?;
+    }
+    Ok(())
+}
+
+/// Entries in `ACCESSION_VERSIONS` are keyed by the bare accession, then a NUL separator, then
+/// the version, so that a prefix scan on the accession returns its versions in order.
+fn accession_version_key(bare_acc: &str, version: u32) -> Vec<u8> {
+    let mut key = bare_acc.as_bytes().to_vec();
+    key.push(0);
+    key.extend_from_slice(&version.to_be_bytes());
+    key
+}
+
+fn insert_accession_version(
+    versions: &sled::Tree,
+    bare_acc: &str,
+    version: u32,
+    taxid: u32,
+) -> TaxonomyResult<()> {
+    let key = accession_version_key(bare_acc, version);
+    // As with bare accessions, the first record of a version wins.
+    if !versions.contains_key(&key)? {
+        versions.insert(key, &taxid.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+/// Retired accessions aren't range-compressed like live ones: there are few enough of them to
+/// store outright, and we don't want to report a live accession as retired just because it sits
+/// between two retired ones.
+fn read_dead_accessions_to_db(
+    pairs: impl Iterator<Item = (String, u32)>,
+    db: &sled::Tree,
+) -> TaxonomyResult<()> {
+    for (accession, taxid) in pairs.dedup_by(|p, q| p.0 == q.0) {
+        db.insert(accession, &taxid.to_le_bytes())?;
+    }
     Ok(())
 }
 
 const ACCESSION_TO_TAXON: &str = "accession_to_taxon";
+const DEAD_ACCESSIONS: &str = "dead_accessions";
+const ACCESSION_RANGES: &str = "accession_ranges";
+const ACCESSION_SET: &str = "accession_set";
+const ACCESSION_VERSIONS: &str = "accession_versions";
 const TAXON_TO_NAME: &str = "taxon_to_name";
+const TAXON_NAMES: &str = "taxon_names";
+const NAME_TO_TAXON: &str = "name_to_taxon";
 const TAXON_TREE: &str = "taxon_tree";
+const TAXON_CHILDREN: &str = "taxon_children";
+const TAXON_INTERVALS: &str = "taxon_intervals";
 const TAXON_RANKS: &str = "taxon_ranks";
-const TAXONOMY_DB_VERSION_KEY: &[u8] = b"taxonomy_db_version";
-const TAXONOMY_DB_VERSION: &[u8] = b"1";
+const MERGED_TAXA: &str = "merged_taxa";
+const DELETED_TAXA: &str = "deleted_taxa";
+/// Entries in `TAXON_NAMES` are keyed by the taxon followed by the position of the name within
+/// names.dmp, so that a prefix scan on the taxon returns its names in their original order.
+fn taxon_names_key(taxon: u32, index: u32) -> [u8; 8] {
+    let mut key = [0; 8];
+    key[..4].copy_from_slice(&taxon.to_le_bytes());
+    key[4..].copy_from_slice(&index.to_be_bytes());
+    key
+}
 
-fn build_new_db(db: sled::Db, source_path: &Path) -> io::Result<TaxonomyDatabase> {
-    let taxdump_file = File::open(source_path.join("taxdump.tar.gz"))?;
-    let taxdump_gz = GzDecoder::new(&taxdump_file);
-    let mut taxdump_archive = Archive::new(taxdump_gz);
-    let mut names: BTreeMap<u32, String> = BTreeMap::new();
-    let mut node_tree: BTreeMap<u32, (u32, Rank)> = BTreeMap::new();
-    for e in taxdump_archive.entries()? {
-        let entry = e?;
-        if entry.path()? == Path::new("names.dmp") {
-            names = read_names_file(entry)?;
-        } else if entry.path()? == Path::new("nodes.dmp") {
-            node_tree = read_nodes_file(entry)?;
+/// Entries in `TAXON_CHILDREN` are keyed by the parent followed by the child, and have no value;
+/// a prefix scan on the parent lists its children.
+fn child_key(parent: u32, child: u32) -> [u8; 8] {
+    let mut key = [0; 8];
+    key[..4].copy_from_slice(&parent.to_le_bytes());
+    key[4..].copy_from_slice(&child.to_be_bytes());
+    key
+}
+
+/// Number every taxon reachable from the root in depth-first pre-order, and pair each taxon's
+/// number with the highest number found in its subtree. Then `a` is a descendant of `b` exactly
+/// when `a`'s number falls within `b`'s interval.
+fn dfs_intervals(node_tree: &BTreeMap<u32, (u32, Rank)>) -> BTreeMap<u32, (u32, u32)> {
+    let mut children: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+    for (&taxon, &(parent, _)) in node_tree {
+        if taxon != parent {
+            children.entry(parent).or_default().push(taxon);
         }
     }
 
-    let taxa_map_dir_path = source_path.join("accession2taxid");
-
-    let taxa_map_dir = std::fs::read_dir(taxa_map_dir_path)?;
-    let fs_iter = taxa_map_dir.filter_map(|f| {
-        let path = f.ok()?.path();
-        let path_str = path.to_str().unwrap();
-        if path_str.ends_with("accession2taxid.gz")
-            || path_str.ends_with("accession2taxid.FULL.gz")
-            || path_str.ends_with("accession2taxid.EXTRA.gz")
-        {
-            Some(GzDecoder::new(File::open(path).ok()?))
-        } else {
-            None
+    let mut intervals = BTreeMap::new();
+    let mut counter = 0;
+    // Each taxon is pushed twice: once to number it on the way down, and once more to close
+    // its interval on the way back up, after all its descendants have been numbered.
+    let mut stack = vec![(1, false)];
+    while let Some((taxon, finished)) = stack.pop() {
+        if finished {
+            intervals
+                .entry(taxon)
+                .and_modify(|(_, last)| *last = counter - 1);
+            continue;
+        }
+        intervals.insert(taxon, (counter, counter));
+        counter += 1;
+        stack.push((taxon, true));
+        if let Some(taxon_children) = children.get(&taxon) {
+            stack.extend(taxon_children.iter().map(|&child| (child, false)));
         }
-    });
+    }
+    intervals
+}
+
+fn interval_value((first, last): (u32, u32)) -> [u8; 8] {
+    let mut value = [0; 8];
+    value[..4].copy_from_slice(&first.to_le_bytes());
+    value[4..].copy_from_slice(&last.to_le_bytes());
+    value
+}
+
+fn parse_interval_value(value: &[u8]) -> Option<(u32, u32)> {
+    let first = value.get(..4)?.try_into().ok()?;
+    let last = value.get(4..)?.try_into().ok()?;
+    Some((u32::from_le_bytes(first), u32::from_le_bytes(last)))
+}
+
+fn parse_taxid(value: &[u8]) -> Option<u32> {
+    value.try_into().ok().map(u32::from_le_bytes)
+}
+
+fn name_value(class: NameClass, name: &str) -> Vec<u8> {
+    let mut value = Vec::with_capacity(name.len() + 1);
+    value.push(class.into());
+    value.extend_from_slice(name.as_bytes());
+    value
+}
+
+/// Entries in `NAME_TO_TAXON` are keyed by the lowercased name, then a NUL separator, then the
+/// taxon and name class, so that the same name can point at several taxa. The original spelling
+/// of the name is kept as the value.
+fn name_index_key(name: &str, taxon: u32, class: NameClass) -> Vec<u8> {
+    let mut key = name.to_lowercase().into_bytes();
+    key.push(0);
+    key.extend_from_slice(&taxon.to_be_bytes());
+    key.push(class.into());
+    key
+}
+
+fn parse_name_index_entry(key: &[u8], value: &[u8]) -> Option<(u32, NameClass, String)> {
+    let (&class, rest) = key.split_last()?;
+    let taxon_bytes = rest.get(rest.len().checked_sub(4)?..)?;
+    let taxon = u32::from_be_bytes(taxon_bytes.try_into().ok()?);
+    let class = NameClass::try_from(class).ok()?;
+    let name = String::from_utf8(value.to_vec()).ok()?;
+    Some((taxon, class, name))
+}
+
+fn parse_name_value(value: &[u8]) -> Option<(NameClass, String)> {
+    let (&class, name) = value.split_first()?;
+    let class = NameClass::try_from(class).ok()?;
+    let name = String::from_utf8(name.to_vec()).ok()?;
+    Some((class, name))
+}
+
+const TAXONOMY_DB_VERSION_KEY: &[u8] = b"taxonomy_db_version";
+/// Only changes when the layout of an existing tree does. Trees added since are flagged by keys
+/// of their own, or are simply empty when absent, so that older databases, such as the one
+/// `install_taxonomy_db` downloads, can still be opened.
+const TAXONOMY_DB_VERSION: &[u8] = b"1";
+/// The version of a database in which every accession is stored in `ACCESSION_TO_TAXON`, and
+/// lookups mustn't assume anything about the accessions between them; see
+/// [`TaxonomySource::interpolate_accessions`]. A crate that only knows version 1 would
+/// interpolate, so it has to refuse these databases rather than open them.
+const NO_INTERPOLATION_DB_VERSION: &[u8] = b"2";
+/// Present only if the database was built with an `ACCESSION_SET` tree.
+const EXACT_MEMBERSHIP_KEY: &[u8] = b"exact_membership";
+/// Present only if the database was built with an `ACCESSION_VERSIONS` tree.
+const ACCESSION_VERSIONS_KEY: &[u8] = b"accession_versions";
+/// Present only if the database was built with `TAXON_NAMES` and `NAME_TO_TAXON` trees.
+const NAME_INDEX_KEY: &[u8] = b"name_index";
+/// Present only if the database was built with `TAXON_CHILDREN` and `TAXON_INTERVALS` trees.
+const TREE_INDEX_KEY: &[u8] = b"tree_index";
+
+fn build_new_db(
+    db: sled::Db,
+    source: &dyn TaxonomySource,
+    options: &BuildOptions,
+) -> TaxonomyResult<TaxonomyDatabase> {
+    let mut node_tree: BTreeMap<u32, (u32, Rank)> = BTreeMap::new();
+    for node in source.nodes()? {
+        let (taxon, parent, rank) = node?;
+        node_tree.insert(taxon, (parent, rank));
+    }
+    let mut names: BTreeMap<u32, Vec<(NameClass, String)>> = BTreeMap::new();
+    for name in source.names()? {
+        let (taxon, class, name) = name?;
+        names.entry(taxon).or_default().push((class, name));
+    }
 
     let accessions = db.open_tree(ACCESSION_TO_TAXON)?;
+    let accession_ranges = db.open_tree(ACCESSION_RANGES)?;
+    let accession_set = if options.exact_membership {
+        db.insert(EXACT_MEMBERSHIP_KEY, &[])?;
+        Some(db.open_tree(ACCESSION_SET)?)
+    } else {
+        None
+    };
+    let accession_versions = if options.accession_versions {
+        db.insert(ACCESSION_VERSIONS_KEY, &[])?;
+        Some(db.open_tree(ACCESSION_VERSIONS)?)
+    } else {
+        None
+    };
+    let interpolate = source.interpolate_accessions();
+    process_results(source.accessions()?, |records| {
+        read_accessions_to_db(
+            records,
+            &accessions,
+            &accession_ranges,
+            accession_set.as_ref(),
+            accession_versions.as_ref(),
+            interpolate,
+        )
+    })??;
 
-    read_accessions_to_db(read_accessions(fs_iter)?, &accessions)?;
+    let dead_accessions = db.open_tree(DEAD_ACCESSIONS)?;
+    process_results(source.dead_accessions()?, |pairs| {
+        read_dead_accessions_to_db(pairs, &dead_accessions)
+    })??;
     let name_map_db = db.open_tree(TAXON_TO_NAME)?;
-    for (k, v) in names.iter() {
-        name_map_db.insert(k.to_le_bytes(), v.as_str())?;
+    let all_names_db = db.open_tree(TAXON_NAMES)?;
+    let name_index_db = db.open_tree(NAME_TO_TAXON)?;
+    db.insert(NAME_INDEX_KEY, &[])?;
+    for (k, taxon_names) in names.iter() {
+        for (i, (class, name)) in taxon_names.iter().enumerate() {
+            if let NameClass::ScientificName = class {
+                name_map_db.insert(k.to_le_bytes(), name.as_str())?;
+            }
+            all_names_db.insert(taxon_names_key(*k, i as u32), name_value(*class, name))?;
+            name_index_db.insert(name_index_key(name, *k, *class), name.as_str())?;
+        }
     }
 
     let node_tree_db = db.open_tree(TAXON_TREE)?;
     let node_ranks_db = db.open_tree(TAXON_RANKS)?;
+    let node_children_db = db.open_tree(TAXON_CHILDREN)?;
+    let node_intervals_db = db.open_tree(TAXON_INTERVALS)?;
+    db.insert(TREE_INDEX_KEY, &[])?;
+    for (k, interval) in dfs_intervals(&node_tree) {
+        node_intervals_db.insert(k.to_le_bytes(), &interval_value(interval))?;
+    }
     for (k, (parent, rank)) in node_tree {
-        node_tree_db.insert(&k.to_le_bytes(), &parent.to_le_bytes())?;
-        node_ranks_db.insert(&k.to_le_bytes(), &[rank as u8])?;
+        node_tree_db.insert(k.to_le_bytes(), &parent.to_le_bytes())?;
+        node_ranks_db.insert(k.to_le_bytes(), &[rank as u8])?;
+        // The root is its own parent, but it isn't its own child.
+        if k != parent {
+            node_children_db.insert(child_key(parent, k), &[])?;
+        }
+    }
+    let merged_db = db.open_tree(MERGED_TAXA)?;
+    for merge in source.merged()? {
+        let (old_taxon, new_taxon) = merge?;
+        merged_db.insert(old_taxon.to_le_bytes(), &new_taxon.to_le_bytes())?;
     }
-    db.insert(TAXONOMY_DB_VERSION_KEY, TAXONOMY_DB_VERSION)?;
+    let deleted_db = db.open_tree(DELETED_TAXA)?;
+    for taxon in source.deleted()? {
+        let taxon = taxon?;
+        deleted_db.insert(taxon.to_le_bytes(), &[])?;
+    }
+    let version = if interpolate {
+        TAXONOMY_DB_VERSION
+    } else {
+        NO_INTERPOLATION_DB_VERSION
+    };
+    db.insert(TAXONOMY_DB_VERSION_KEY, version)?;
 
     db.flush()?;
     Ok(TaxonomyDatabase {
         accession_to_taxon: accessions,
+        accession_ranges,
+        dead_accessions,
+        accession_set,
+        accession_versions,
         taxon_to_name: name_map_db,
+        taxon_names: Some(all_names_db),
+        name_to_taxon: Some(name_index_db),
         taxon_tree: node_tree_db,
+        taxon_children: Some(node_children_db),
+        taxon_intervals: Some(node_intervals_db),
         taxon_ranks: node_ranks_db,
+        merged_taxa: merged_db,
+        deleted_taxa: deleted_db,
+        interpolate,
+        overlay: Overlay::new(),
     })
 }
 
-pub fn unzip_db<Ps: AsRef<Path>, Pt: AsRef<Path>>(source: Ps, target: Pt) -> io::Result<()> {
+pub fn unzip_db<Ps: AsRef<Path>, Pt: AsRef<Path>>(source: Ps, target: Pt) -> TaxonomyResult<()> {
     let source_file = File::open(source)?;
     let source_gz = GzDecoder::new(&source_file);
     let mut archive = Archive::new(source_gz);
-    archive.unpack(target)
+    Ok(archive.unpack(target)?)
 }
 
-fn open_existing(db_config: sled::Config) -> io::Result<TaxonomyDatabase> {
-    let db = db_config.open()?;
-    if let Ok(Some(v)) = db.get(TAXONOMY_DB_VERSION_KEY) {
-        if &(*v) != TAXONOMY_DB_VERSION {
-            return Err(data_error("Taxonomy database has incompatible version"));
+fn open_existing(db: sled::Db) -> TaxonomyResult<TaxonomyDatabase> {
+    let mut interpolate = true;
+    if let Some(v) = db.get(TAXONOMY_DB_VERSION_KEY)? {
+        if &(*v) == NO_INTERPOLATION_DB_VERSION {
+            interpolate = false;
+        } else if &(*v) != TAXONOMY_DB_VERSION {
+            return Err(TaxonomyError::IncompatibleVersion {
+                found: String::from_utf8_lossy(&v).into_owned(),
+                expected: String::from_utf8_lossy(TAXONOMY_DB_VERSION).into_owned(),
+            });
         }
     }
+    let optional_tree = |key: &[u8], tree_name: &str| -> TaxonomyResult<Option<sled::Tree>> {
+        Ok(if db.contains_key(key)? {
+            Some(db.open_tree(tree_name)?)
+        } else {
+            None
+        })
+    };
     Ok(TaxonomyDatabase {
         accession_to_taxon: db.open_tree(ACCESSION_TO_TAXON)?,
+        accession_ranges: db.open_tree(ACCESSION_RANGES)?,
+        dead_accessions: db.open_tree(DEAD_ACCESSIONS)?,
+        accession_set: optional_tree(EXACT_MEMBERSHIP_KEY, ACCESSION_SET)?,
+        accession_versions: optional_tree(ACCESSION_VERSIONS_KEY, ACCESSION_VERSIONS)?,
         taxon_to_name: db.open_tree(TAXON_TO_NAME)?,
+        taxon_names: optional_tree(NAME_INDEX_KEY, TAXON_NAMES)?,
+        name_to_taxon: optional_tree(NAME_INDEX_KEY, NAME_TO_TAXON)?,
         taxon_tree: db.open_tree(TAXON_TREE)?,
+        taxon_children: optional_tree(TREE_INDEX_KEY, TAXON_CHILDREN)?,
+        taxon_intervals: optional_tree(TREE_INDEX_KEY, TAXON_INTERVALS)?,
         taxon_ranks: db.open_tree(TAXON_RANKS)?,
+        merged_taxa: db.open_tree(MERGED_TAXA)?,
+        deleted_taxa: db.open_tree(DELETED_TAXA)?,
+        interpolate,
+        overlay: Overlay::new(),
     })
 }
 
@@ -217,6 +511,7 @@ impl TaxonomyDatabaseConfig {
             source: TaxonomyDatabaseSource::FromExisting,
             cache_size: None,
             location: None,
+            build_options: BuildOptions::default(),
         }
     }
 
@@ -235,11 +530,28 @@ impl TaxonomyDatabaseConfig {
         self
     }
 
-    pub fn build(&self) -> std::io::Result<TaxonomyDatabase> {
+    /// When building a new database, record every accession individually as well as the
+    /// compressed ranges. This makes the database considerably larger, but allows
+    /// [`TaxonomyDatabase::query_accession_strict`].
+    pub fn exact_membership(mut self, exact_membership: bool) -> Self {
+        self.build_options.exact_membership = exact_membership;
+        self
+    }
+
+    /// When building a new database, record the taxon of each version of each accession, as
+    /// well as of the accession as a whole. This allows version-specific lookups with
+    /// [`TaxonomyDatabase::query_accession_version`].
+    pub fn accession_versions(mut self, accession_versions: bool) -> Self {
+        self.build_options.accession_versions = accession_versions;
+        self
+    }
+
+    pub fn build(&self) -> TaxonomyResult<TaxonomyDatabase> {
         let db_path = if let Some(ref p) = &self.location {
             p.to_owned()
         } else {
-            xdg::BaseDirectories::with_prefix("taxonomy_lookup")?
+            xdg::BaseDirectories::with_prefix("taxonomy_lookup")
+                .map_err(io::Error::from)?
                 .place_data_file("taxonomy.sled")?
         };
 
@@ -251,16 +563,25 @@ impl TaxonomyDatabaseConfig {
             db_config = db_config.cache_capacity(cache_size);
         }
 
+        let build_from = |source: &dyn TaxonomySource| {
+            let _ = std::fs::remove_dir_all(&db_path);
+            let db = db_config.open()?;
+            build_new_db(db, source, &self.build_options)
+        };
+
         Ok(match &self.source {
             TaxonomyDatabaseSource::FromFiles(ref path) => {
-                let _ = std::fs::remove_dir_all(&db_path);
-                let db = db_config.open()?;
-                build_new_db(db, path)?
+                build_from(&NcbiSource::new(path.to_owned()))?
             }
-            TaxonomyDatabaseSource::FromExisting => open_existing(db_config)?,
+            TaxonomyDatabaseSource::FromFlatfiles {
+                ref taxonomy,
+                ref flatfiles,
+            } => build_from(&NcbiSource::new(taxonomy.to_owned()).flatfiles(flatfiles.to_owned()))?,
+            TaxonomyDatabaseSource::FromSource(source) => build_from(source.as_ref())?,
+            TaxonomyDatabaseSource::FromExisting => open_existing(db_config.open()?)?,
             TaxonomyDatabaseSource::FromGzipped(ref path) => {
                 unzip_db(path, &db_path)?;
-                open_existing(db_config)?
+                open_existing(db_config.open()?)?
             }
         })
     }
@@ -274,92 +595,1298 @@ impl Default for TaxonomyDatabaseConfig {
 
 pub struct TaxonomyDatabase {
     accession_to_taxon: sled::Tree,
+    accession_ranges: sled::Tree,
+    dead_accessions: sled::Tree,
+    /// Every live accession, if the database was built with
+    /// [`TaxonomyDatabaseConfig::exact_membership`].
+    accession_set: Option<sled::Tree>,
+    /// The taxon of every version of every live accession, if the database was built with
+    /// [`TaxonomyDatabaseConfig::accession_versions`].
+    accession_versions: Option<sled::Tree>,
     taxon_to_name: sled::Tree,
+    /// Every name of every taxon, and the index of names, unless the database predates them.
+    taxon_names: Option<sled::Tree>,
+    name_to_taxon: Option<sled::Tree>,
     taxon_tree: sled::Tree,
+    /// The indexes behind tree queries like [`TaxonomyDatabase::children`], unless the database
+    /// predates them.
+    taxon_children: Option<sled::Tree>,
+    taxon_intervals: Option<sled::Tree>,
     taxon_ranks: sled::Tree,
+    merged_taxa: sled::Tree,
+    deleted_taxa: sled::Tree,
+    /// Whether accessions falling within a run of stored accessions belong to the run's taxon;
+    /// see [`TaxonomySource::interpolate_accessions`].
+    interpolate: bool,
+    overlay: Overlay,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TaxonomyInfo {
+    /// The taxon and each of its ancestors.
+    pub lineage: Lineage,
+    /// Set if the taxon that was asked for has since been merged into another, in which case
+    /// this is the ID originally asked for, and `lineage` describes its replacement.
+    pub merged_from: Option<u32>,
+    /// Set if any part of the answer came from the database's [`Overlay`], rather than from the
+    /// database itself.
+    pub local: bool,
+}
+
+/// How an accession lookup found the accession.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AccessionMatch {
+    /// The accession itself is stored in the database.
+    Exact,
+    /// The accession isn't stored itself, but falls between two stored accessions belonging to
+    /// the same taxon, so is assumed to belong to it too.
+    Interpolated {
+        lower: String,
+        upper: String,
+        /// Whether the accession was present in the source files; see
+        /// [`TaxonomyDatabaseConfig::exact_membership`]. `None` if the database doesn't know.
+        confirmed: Option<bool>,
+    },
+    /// The accession falls within a range of accessions, like "AAAA01000001-AAAA01099999",
+    /// that was recorded as a whole.
+    Range { start: String, end: String },
+    /// The accession was assigned to its taxon by the database's [`Overlay`].
+    Local,
+}
+
+/// How [`TaxonomyDatabase::query_accession_explained`] should look accessions up.
+#[derive(Copy, Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LookupOptions {
+    /// Only answer for accessions present in the source files, as
+    /// [`TaxonomyDatabase::query_accession_strict`] does.
+    pub strict: bool,
+    /// Only answer for the exact version of the accession given, as
+    /// [`TaxonomyDatabase::query_accession_version`] does.
+    pub exact_version: bool,
+}
+
+/// How [`TaxonomyDatabase::query_accession_explained`] arrived at its answer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LookupExplanation {
+    pub accession_match: AccessionMatch,
+    /// The version of the accession that matched, if the database records versions.
+    pub version: Option<u32>,
+    /// The taxon recorded for the accession.
+    pub taxon: u32,
+    /// Set if the recorded taxon has since been merged into another, in which case this is the
+    /// taxon that the answer actually describes.
+    pub merged_into: Option<u32>,
+}
+
+/// A single node of the taxonomy tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Taxon {
+    pub id: u32,
+    pub rank: Rank,
+    /// The taxon's scientific name.
+    pub name: String,
+}
+
+/// How [`TaxonomyDatabase::find_by_name`] compares the query against stored names.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NameMatchMode {
+    /// The name must match exactly, including case.
+    Exact,
+    /// The name must match, ignoring case.
+    CaseInsensitive,
+    /// The name must start with the query, ignoring case.
+    Prefix,
+}
+
+/// A taxon found by [`TaxonomyDatabase::find_by_name`], along with the name that matched.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NameMatch {
+    pub taxon: u32,
+    pub name: String,
+    pub name_class: NameClass,
+    pub rank: Rank,
+}
+
+/// A candidate taxon suggested by [`TaxonomyDatabase::fuzzy_find_by_name`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FuzzyMatch {
+    pub taxon: u32,
+    /// The stored name that was closest to the query.
+    pub name: String,
+    pub name_class: NameClass,
+    pub rank: Rank,
+    /// Similarity between the query and `name`, from 0 (nothing alike) to 1 (identical,
+    /// ignoring case).
+    pub score: f64,
+    /// As in [`TaxonomyInfo::lineage`].
+    pub lineage: Lineage,
+}
+
+/// A lazy, depth-first walk over the taxa below some taxon, produced by
+/// [`TaxonomyDatabase::descendants`]. The starting taxon itself isn't included.
+pub struct Descendants<'a> {
+    db: &'a TaxonomyDatabase,
+    /// Taxa still to be visited, along with their depth below the starting taxon.
+    stack: Vec<(u32, usize)>,
+    max_depth: Option<usize>,
+    stop_rank: Option<Rank>,
+}
+
+impl<'a> Descendants<'a> {
+    /// Don't go more than `depth` levels below the starting taxon. Depth counts from the starting
+    /// taxon's children, so a depth of 1 gives just them, and a depth of 0 gives nothing.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Don't go below taxa of the given rank. Those taxa are still produced, but their
+    /// descendants aren't.
+    pub fn stop_at_rank(mut self, rank: Rank) -> Self {
+        self.stop_rank = Some(rank);
+        self
+    }
+
+    fn expand(&mut self, taxon: u32, depth: usize) -> TaxonomyResult<()> {
+        if self.max_depth.is_some_and(|max| depth >= max) {
+            return Ok(());
+        }
+        if let Some(stop_rank) = self.stop_rank {
+            if self.db.rank(taxon)? == stop_rank {
+                return Ok(());
+            }
+        }
+        let children = self.db.children(taxon)?;
+        // Reversed, so that children come off the stack in ascending order.
+        self.stack
+            .extend(children.into_iter().rev().map(|child| (child, depth + 1)));
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for Descendants<'a> {
+    type Item = TaxonomyResult<u32>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (taxon, depth) = self.stack.pop()?;
+        // Only the children, pushed before any limit was set, can lie beyond it.
+        if self.max_depth.is_some_and(|max| depth > max) {
+            self.stack.clear();
+            return None;
+        }
+        Some(self.expand(taxon, depth).map(|()| taxon))
+    }
+}
+
+/// Candidates scoring below this are too dissimilar to be worth suggesting.
+const MIN_FUZZY_SCORE: f64 = 0.5;
+
+/// Similarity of two strings based on their edit distance, from 0 to 1.
+fn similarity(a: &str, b: &str) -> f64 {
+    let len = a.chars().count().max(b.chars().count());
+    if len == 0 {
+        return 1.0;
+    }
+    1.0 - strsim::osa_distance(a, b) as f64 / len as f64
+}
+
+/// Similarity of two (already lowercased) names. Besides a straight edit-distance comparison,
+/// we compare the query against the start of the name, so that partially remembered names
+/// ("bacillus anthr") still find their taxon; such partial matches are discounted slightly so
+/// that a complete match always ranks higher.
+fn fuzzy_score(query: &str, query_len: usize, name: &str) -> f64 {
+    let name_len = name.chars().count();
+    // The edit distance is at least the difference in length, so a name this much shorter than
+    // the query can't score well enough, and there's no point computing it.
+    if (name_len as f64) < query_len as f64 * MIN_FUZZY_SCORE {
+        return 0.0;
+    }
+    let full = similarity(name, query);
+    let partial = if name_len > query_len {
+        let name_prefix: String = name.chars().take(query_len).collect();
+        0.9 * similarity(&name_prefix, query)
+    } else {
+        0.0
+    };
+    full.max(partial)
 }
 
-#[derive(Debug)]
-pub struct TaxonomyInfo(pub Vec<(Rank, String)>);
+/// Merge several sorted streams of keys into one, without duplicates. Errors sort first, so
+/// that they come out as soon as they're hit.
+pub(crate) fn merge_sorted_keys<I>(
+    iters: impl IntoIterator<Item = I>,
+) -> impl Iterator<Item = TaxonomyResult<String>>
+where
+    I: Iterator<Item = TaxonomyResult<String>>,
+{
+    iters
+        .into_iter()
+        .kmerge_by(errors_first(|p, q| p < q))
+        .dedup_by(|p, q| matches!((p, q), (Ok(p), Ok(q)) if p == q))
+}
 
-// TODO throughout here, there's a bunch of annoying repetitive error stuff. Probably
-// I should just make this less bad somehow.
 impl TaxonomyDatabase {
-    pub fn rank(&self, taxon: u32) -> std::io::Result<Rank> {
-        let content = self.taxon_ranks.get(taxon.to_le_bytes())?.ok_or_else(|| {
-            data_error("Corrupted taxonomy rank information: Could not find node")
-        })?;
-        let rank_bytes: [u8; 1] = (*content).try_into().map_err(|_| {
-            data_error("Corrupted taxonomy rank information: Could not convert to bytes")
-        })?;
-        rank_bytes[0].try_into().map_err(|_| {
-            data_error("Corrupted taxonomy rank information: Could not convert to enum")
+    /// Local taxa and accessions layered on top of the database.
+    pub fn overlay(&self) -> &Overlay {
+        &self.overlay
+    }
+
+    /// The same database, without the overlay.
+    pub(crate) fn without_overlay(&self) -> TaxonomyDatabase {
+        TaxonomyDatabase {
+            accession_to_taxon: self.accession_to_taxon.clone(),
+            accession_ranges: self.accession_ranges.clone(),
+            dead_accessions: self.dead_accessions.clone(),
+            accession_set: self.accession_set.clone(),
+            accession_versions: self.accession_versions.clone(),
+            taxon_to_name: self.taxon_to_name.clone(),
+            taxon_names: self.taxon_names.clone(),
+            name_to_taxon: self.name_to_taxon.clone(),
+            taxon_tree: self.taxon_tree.clone(),
+            taxon_children: self.taxon_children.clone(),
+            taxon_intervals: self.taxon_intervals.clone(),
+            taxon_ranks: self.taxon_ranks.clone(),
+            merged_taxa: self.merged_taxa.clone(),
+            deleted_taxa: self.deleted_taxa.clone(),
+            interpolate: self.interpolate,
+            overlay: Overlay::new(),
+        }
+    }
+
+    /// Layer `overlay` on top of the database, replacing any overlay already there. This fails,
+    /// leaving the database as it was, if an overlay taxon's parent is in neither the overlay
+    /// nor the database, if its lineage loops back round through the database's taxa, or if an
+    /// overlay accession's taxon is in neither. For an overlay read from a file, the last is
+    /// reported as a parse error at the first such accession's line.
+    pub fn set_overlay(&mut self, overlay: Overlay) -> TaxonomyResult<()> {
+        let previous = std::mem::replace(&mut self.overlay, overlay);
+        let checked = self
+            .overlay
+            .taxa()
+            .try_for_each(|taxon| self.ancestors(taxon).map(|_| ()))
+            .and_then(|()| self.check_overlay_accessions());
+        if checked.is_err() {
+            self.overlay = previous;
+        }
+        checked
+    }
+
+    /// Fail if any of the overlay's accessions belongs to a taxon in neither the overlay nor the
+    /// database.
+    fn check_overlay_accessions(&self) -> TaxonomyResult<()> {
+        let mut unknown = vec![];
+        for (accession, taxon, line) in self.overlay.accessions() {
+            if !self.overlay.contains_taxon(taxon)
+                && !self.taxon_tree.contains_key(taxon.to_le_bytes())?
+            {
+                unknown.push((line, accession, taxon));
+            }
+        }
+        let (line, accession, taxon) = match unknown.into_iter().min() {
+            Some(first) => first,
+            None => return Ok(()),
+        };
+        let error = self.missing_taxon(taxon);
+        Err(match (self.overlay.file(), line) {
+            (Some(file), Some(line)) => {
+                TaxonomyError::parse(file, line, format!("Accession {}: {}", accession, error))
+            }
+            _ => error,
         })
     }
 
-    pub fn name(&self, taxon: u32) -> std::io::Result<String> {
+    /// The error to report for a taxon that isn't in the tree: it may have been deleted, or we
+    /// may simply never have heard of it.
+    fn missing_taxon(&self, taxon: u32) -> TaxonomyError {
+        match self.deleted_taxa.contains_key(taxon.to_le_bytes()) {
+            Ok(true) => TaxonomyError::DeletedTaxon(taxon),
+            Ok(false) => TaxonomyError::UnknownTaxon(taxon),
+            Err(e) => e.into(),
+        }
+    }
+
+    pub fn rank(&self, taxon: u32) -> TaxonomyResult<Rank> {
+        if let Some(rank) = self.overlay.rank(taxon) {
+            return Ok(rank);
+        }
+        let content = self
+            .taxon_ranks
+            .get(taxon.to_le_bytes())?
+            .ok_or_else(|| self.missing_taxon(taxon))?;
+        match *content {
+            [rank_byte] => rank_byte
+                .try_into()
+                .map_err(|_| TaxonomyError::corrupted(TAXON_RANKS, taxon)),
+            _ => Err(TaxonomyError::corrupted(TAXON_RANKS, taxon)),
+        }
+    }
+
+    pub fn name(&self, taxon: u32) -> TaxonomyResult<String> {
+        if let Some(name) = self.overlay.name(taxon) {
+            return Ok(name.to_string());
+        }
         let content = self
             .taxon_to_name
             .get(taxon.to_le_bytes())?
-            .ok_or_else(|| data_error("Corrupted taxonomy name information: node not found"))?;
-        String::from_utf8((*content).try_into().map_err(|_| {
-            data_error("Corrupted taxonomy name information: could not convert to bytes")
-        })?)
-        .map_err(|_| data_error("Corrupted taxonomy name information: invalid utf8"))
-    }
-
-    pub fn query_taxon(&self, taxon: u32) -> std::io::Result<TaxonomyInfo> {
-        let taxon_bytes = taxon.to_le_bytes();
-        let mut ancestor_taxons = vec![];
-        let mut ancestor_id = taxon_bytes;
-        while ancestor_id != 1u32.to_le_bytes() {
-            ancestor_taxons.push(u32::from_le_bytes(ancestor_id));
-            if let Ok(Some(content)) = self.taxon_tree.get(ancestor_id) {
-                ancestor_id = (*content).try_into().map_err(|_| {
-                    data_error(
-                        "Corrupted taxonomy node information: could not read ancestor id bytes",
-                    )
-                })?
-            } else {
-                return Err(data_error(
-                    "Corrupted taxonomy node information: could not find ancestor",
-                ));
+            .ok_or_else(|| self.missing_taxon(taxon))?;
+        String::from_utf8(content.to_vec())
+            .map_err(|_| TaxonomyError::corrupted(TAXON_TO_NAME, taxon))
+    }
+
+    /// Every name recorded for the taxon in names.dmp, in the order they appear there. Overlay
+    /// taxa only have their scientific name.
+    pub fn names(&self, taxon: u32) -> TaxonomyResult<Vec<(NameClass, String)>> {
+        if let Some(name) = self.overlay.name(taxon) {
+            return Ok(vec![(NameClass::ScientificName, name.to_string())]);
+        }
+        let taxon_names = match &self.taxon_names {
+            Some(taxon_names) => taxon_names,
+            // Older databases only kept scientific names.
+            None => return Ok(vec![(NameClass::ScientificName, self.name(taxon)?)]),
+        };
+        let mut result = vec![];
+        for entry in taxon_names.scan_prefix(taxon.to_le_bytes()) {
+            let (_, value) = entry?;
+            let name = parse_name_value(&value)
+                .ok_or_else(|| TaxonomyError::corrupted(TAXON_NAMES, taxon))?;
+            result.push(name);
+        }
+        if result.is_empty() && !self.taxon_ranks.contains_key(taxon.to_le_bytes())? {
+            return Err(self.missing_taxon(taxon));
+        }
+        Ok(result)
+    }
+
+    /// The taxon's preferred common name, if it has one. GenBank's choice of common name wins
+    /// over any others.
+    pub fn common_name(&self, taxon: u32) -> TaxonomyResult<Option<String>> {
+        let names = self.names(taxon)?;
+        let find = |wanted: NameClass| {
+            names
+                .iter()
+                .find(|(class, _)| *class == wanted)
+                .map(|(_, name)| name.clone())
+        };
+        Ok(find(NameClass::GenbankCommonName).or_else(|| find(NameClass::CommonName)))
+    }
+
+    fn name_index(&self) -> TaxonomyResult<&sled::Tree> {
+        self.name_to_taxon
+            .as_ref()
+            .ok_or(TaxonomyError::IndexUnavailable(NAME_TO_TAXON))
+    }
+
+    /// Every taxon with a name (of any class) matching `query`.
+    pub fn find_by_name(&self, query: &str, mode: NameMatchMode) -> TaxonomyResult<Vec<NameMatch>> {
+        let mut prefix = query.to_lowercase().into_bytes();
+        if mode != NameMatchMode::Prefix {
+            prefix.push(0);
+        }
+
+        let mut result = vec![];
+        for entry in self.name_index()?.scan_prefix(prefix) {
+            let (key, value) = entry?;
+            let (taxon, name_class, name) = parse_name_index_entry(&key, &value)
+                .ok_or_else(|| TaxonomyError::corrupted(NAME_TO_TAXON, query))?;
+            if mode == NameMatchMode::Exact && name != query {
+                continue;
             }
+            result.push(NameMatch {
+                taxon,
+                name,
+                name_class,
+                rank: self.rank(taxon)?,
+            });
         }
+        Ok(result)
+    }
 
+    /// Suggest up to `limit` taxa whose names resemble `query`, best first. This scans every
+    /// stored name, so it's much slower than [`TaxonomyDatabase::find_by_name`].
+    pub fn fuzzy_find_by_name(&self, query: &str, limit: usize) -> TaxonomyResult<Vec<FuzzyMatch>> {
+        let query = query.to_lowercase();
+        let query_len = query.chars().count();
+        // The best-scoring name for each taxon.
+        let mut best: BTreeMap<u32, (f64, NameClass, String)> = BTreeMap::new();
+        for entry in self.name_index()?.iter() {
+            let (key, value) = entry?;
+            let (taxon, name_class, name) =
+                parse_name_index_entry(&key, &value).ok_or_else(|| {
+                    TaxonomyError::corrupted(NAME_TO_TAXON, String::from_utf8_lossy(&key))
+                })?;
+            let score = fuzzy_score(&query, query_len, &name.to_lowercase());
+            if score < MIN_FUZZY_SCORE {
+                continue;
+            }
+            match best.get(&taxon) {
+                Some((best_score, _, _)) if *best_score >= score => {}
+                _ => {
+                    best.insert(taxon, (score, name_class, name));
+                }
+            }
+        }
+
+        let mut candidates = best.into_iter().collect::<Vec<_>>();
+        candidates.sort_by(|(_, (a, _, _)), (_, (b, _, _))| b.total_cmp(a));
+        candidates.truncate(limit);
+
+        let mut result = vec![];
+        for (taxon, (score, name_class, name)) in candidates {
+            result.push(FuzzyMatch {
+                taxon,
+                name,
+                name_class,
+                rank: self.rank(taxon)?,
+                score,
+                lineage: self.query_taxon(taxon)?.lineage,
+            });
+        }
+        Ok(result)
+    }
+
+    /// The taxa directly below `taxon`, in ascending order of taxon ID.
+    pub fn children(&self, taxon: u32) -> TaxonomyResult<Vec<u32>> {
+        let mut result = vec![];
+        let taxon_children = self
+            .taxon_children
+            .as_ref()
+            .ok_or(TaxonomyError::IndexUnavailable(TAXON_CHILDREN))?;
+        for entry in taxon_children.scan_prefix(taxon.to_le_bytes()) {
+            let (key, _) = entry?;
+            let child_bytes = key[4..]
+                .try_into()
+                .map_err(|_| TaxonomyError::corrupted(TAXON_CHILDREN, taxon))?;
+            result.push(u32::from_be_bytes(child_bytes));
+        }
+        if result.is_empty() && !self.taxon_ranks.contains_key(taxon.to_le_bytes())? {
+            return Err(self.missing_taxon(taxon));
+        }
+        Ok(result)
+    }
+
+    /// Every taxon below `taxon`, visited depth-first. Use [`Descendants::max_depth`] and
+    /// [`Descendants::stop_at_rank`] to limit how far down the walk goes.
+    pub fn descendants(&self, taxon: u32) -> TaxonomyResult<Descendants<'_>> {
+        let mut descendants = Descendants {
+            db: self,
+            stack: vec![],
+            max_depth: None,
+            stop_rank: None,
+        };
+        // Only the children are fetched up front, so that limits set afterwards still apply
+        // to them. Checking for them here also rejects unknown taxa immediately.
+        let children = self.children(taxon)?;
+        descendants
+            .stack
+            .extend(children.into_iter().rev().map(|child| (child, 1)));
+        Ok(descendants)
+    }
+
+    /// The number of taxa in the subtree rooted at `taxon`, including `taxon` itself. Merged
+    /// taxa count as the taxon they were merged into.
+    pub fn subtree_size(&self, taxon: u32) -> TaxonomyResult<usize> {
+        let (first, last) = self.interval(taxon)?;
+        Ok((last - first) as usize + 1)
+    }
+
+    /// The taxon's depth-first interval; see `dfs_intervals`. Merged taxa are followed first.
+    fn interval(&self, taxon: u32) -> TaxonomyResult<(u32, u32)> {
+        let taxon = self.resolve_taxid(taxon)?;
+        let taxon_intervals = self
+            .taxon_intervals
+            .as_ref()
+            .ok_or(TaxonomyError::IndexUnavailable(TAXON_INTERVALS))?;
+        let content = match taxon_intervals.get(taxon.to_le_bytes())? {
+            Some(content) => content,
+            None if self.taxon_ranks.contains_key(taxon.to_le_bytes())? => {
+                return Err(TaxonomyError::corrupted(TAXON_INTERVALS, taxon))
+            }
+            None => return Err(self.missing_taxon(taxon)),
+        };
+        parse_interval_value(&content)
+            .ok_or_else(|| TaxonomyError::corrupted(TAXON_INTERVALS, taxon))
+    }
+
+    /// Whether `taxon` lies within the clade rooted at `ancestor`. Every taxon lies within its
+    /// own clade, and merged taxa count as the taxon they were merged into. This takes two
+    /// lookups, regardless of how far apart the taxa are.
+    pub fn is_descendant(&self, taxon: u32, ancestor: u32) -> TaxonomyResult<bool> {
+        let (taxon_first, _) = self.interval(taxon)?;
+        let (ancestor_first, ancestor_last) = self.interval(ancestor)?;
+        Ok(ancestor_first <= taxon_first && taxon_first <= ancestor_last)
+    }
+
+    /// Every taxon in the database's tree, in no particular order. Taxa that only exist in the
+    /// overlay aren't included.
+    pub fn taxa(&self) -> impl Iterator<Item = TaxonomyResult<u32>> + '_ {
+        self.taxon_tree.iter().keys().map(|key| {
+            let key = key?;
+            parse_taxid(&key)
+                .ok_or_else(|| TaxonomyError::corrupted(TAXON_TREE, format!("{:?}", key)))
+        })
+    }
+
+    /// Every accession stored in the database, sorted: the ends of each run of accessions
+    /// sharing a taxon, the start of each range and, if the database was built with
+    /// [`TaxonomyDatabaseConfig::exact_membership`], every other accession too. Looking each of
+    /// these up is enough to cover everything the database knows about accessions.
+    pub(crate) fn stored_accessions(&self) -> impl Iterator<Item = TaxonomyResult<String>> + '_ {
+        let mut trees = vec![
+            (ACCESSION_TO_TAXON, &self.accession_to_taxon),
+            (ACCESSION_RANGES, &self.accession_ranges),
+        ];
+        if let Some(accession_set) = &self.accession_set {
+            trees.push((ACCESSION_SET, accession_set));
+        }
+        let key_iters = trees.into_iter().map(|(tree_name, tree)| {
+            tree.iter().keys().map(move |key| {
+                let key = key?;
+                String::from_utf8(key.to_vec())
+                    .map_err(|_| TaxonomyError::corrupted(tree_name, format!("{:?}", key)))
+            })
+        });
+        merge_sorted_keys(key_iters)
+    }
+
+    /// Whether every live accession is stored, rather than just the ends of each run: either
+    /// the database was built with [`TaxonomyDatabaseConfig::exact_membership`], or its source
+    /// doesn't interpolate between accessions.
+    pub(crate) fn stores_every_accession(&self) -> bool {
+        self.accession_set.is_some() || !self.interpolate
+    }
+
+    /// Every live accession stored in the database, as (bare accession, version, taxon), sorted
+    /// by accession. As in [`TaxonomyDatabase::stored_accessions`], this only lists every
+    /// accession if the database was built with [`TaxonomyDatabaseConfig::exact_membership`];
+    /// otherwise it's just the ends of each run, which is enough to rebuild an equivalent
+    /// database. Recorded ranges come out whole, like "AAAA01000001-AAAA01099999". Versions are
+    /// only known if the database was built with [`TaxonomyDatabaseConfig::accession_versions`].
+    /// The overlay is ignored.
+    pub(crate) fn accession_records(
+        &self,
+    ) -> impl Iterator<Item = TaxonomyResult<(String, Option<u32>, u32)>> + '_ {
+        let mut trees = vec![(ACCESSION_TO_TAXON, &self.accession_to_taxon)];
+        if let Some(accession_set) = &self.accession_set {
+            trees.push((ACCESSION_SET, accession_set));
+        }
+        let key_iters = trees.into_iter().map(|(tree_name, tree)| {
+            tree.iter().keys().map(move |key| {
+                let key = key?;
+                String::from_utf8(key.to_vec())
+                    .map_err(|_| TaxonomyError::corrupted(tree_name, format!("{:?}", key)))
+            })
+        });
+        let singles = merge_sorted_keys(key_iters).map(|accession| {
+            let accession = accession?;
+            // Every accession lies within a run, so it belongs to the taxon of the closest
+            // endstop at or before it.
+            let taxon = match self
+                .accession_to_taxon
+                .range(..=accession.as_bytes())
+                .next_back()
+            {
+                Some(entry) => parse_taxid(&entry?.1),
+                None => None,
+            }
+            .ok_or_else(|| TaxonomyError::corrupted(ACCESSION_SET, &accession))?;
+            let version = self.matched_version(&accession, None, taxon)?;
+            Ok((accession, version, taxon))
+        });
+        let ranges = self.accession_ranges.iter().map(|entry| {
+            let (start, value) = entry?;
+            let corrupted = || TaxonomyError::corrupted(ACCESSION_RANGES, format!("{:?}", start));
+            let (taxon, end) = parse_range_value(&value).ok_or_else(corrupted)?;
+            let range = format!(
+                "{}-{}",
+                std::str::from_utf8(&start).map_err(|_| corrupted())?,
+                std::str::from_utf8(end).map_err(|_| corrupted())?
+            );
+            Ok((range, None, taxon))
+        });
+        singles.merge_by(ranges, errors_first(|p: &(String, _, _), q| p.0 < q.0))
+    }
+
+    /// Every retired accession, as (bare accession, last taxon), sorted by accession.
+    pub(crate) fn dead_accession_records(
+        &self,
+    ) -> impl Iterator<Item = TaxonomyResult<(String, u32)>> + '_ {
+        self.dead_accessions.iter().map(|entry| {
+            let (key, value) = entry?;
+            let corrupted = || TaxonomyError::corrupted(DEAD_ACCESSIONS, format!("{:?}", key));
+            let accession = String::from_utf8(key.to_vec()).map_err(|_| corrupted())?;
+            let taxon = parse_taxid(&value).ok_or_else(corrupted)?;
+            Ok((accession, taxon))
+        })
+    }
+
+    /// Every merged taxon, as (old taxon, new taxon), in no particular order.
+    pub(crate) fn merged_taxa(&self) -> impl Iterator<Item = TaxonomyResult<(u32, u32)>> + '_ {
+        self.merged_taxa.iter().map(|entry| {
+            let (key, value) = entry?;
+            let corrupted = || TaxonomyError::corrupted(MERGED_TAXA, format!("{:?}", key));
+            Ok((
+                parse_taxid(&key).ok_or_else(corrupted)?,
+                parse_taxid(&value).ok_or_else(corrupted)?,
+            ))
+        })
+    }
+
+    /// Every deleted taxon, in no particular order.
+    pub(crate) fn deleted_taxa(&self) -> impl Iterator<Item = TaxonomyResult<u32>> + '_ {
+        self.deleted_taxa.iter().keys().map(|key| {
+            let key = key?;
+            parse_taxid(&key)
+                .ok_or_else(|| TaxonomyError::corrupted(DELETED_TAXA, format!("{:?}", key)))
+        })
+    }
+
+    /// The taxon directly above `taxon`. The root (taxon 1) is its own parent.
+    pub fn parent(&self, taxon: u32) -> TaxonomyResult<u32> {
+        if let Some(parent) = self.overlay.parent(taxon) {
+            return Ok(parent);
+        }
+        let content = self
+            .taxon_tree
+            .get(taxon.to_le_bytes())?
+            .ok_or_else(|| self.missing_taxon(taxon))?;
+        (*content)
+            .try_into()
+            .map(u32::from_le_bytes)
+            .map_err(|_| TaxonomyError::corrupted(TAXON_TREE, taxon))
+    }
+
+    /// The path from `taxon` up to (and including) the root.
+    pub(crate) fn ancestors(&self, taxon: u32) -> TaxonomyResult<Vec<u32>> {
+        let mut ancestor_taxons = vec![taxon];
+        let mut ancestor_id = taxon;
+        // The database's own tree has no loops, but the overlay can introduce them.
+        let mut seen = HashSet::from([taxon]);
+        while ancestor_id != 1 {
+            ancestor_id = match self.parent(ancestor_id) {
+                Ok(parent) => parent,
+                // The queried taxon itself may simply not exist, but every ancestor we reach
+                // by following parent links must.
+                Err(TaxonomyError::UnknownTaxon(_) | TaxonomyError::DeletedTaxon(_))
+                    if ancestor_id != taxon =>
+                {
+                    let child = ancestor_taxons[ancestor_taxons.len() - 2];
+                    return Err(if self.overlay.contains_taxon(child) {
+                        TaxonomyError::OverlayUnknownParent {
+                            taxon: child,
+                            parent: ancestor_id,
+                        }
+                    } else {
+                        TaxonomyError::corrupted(TAXON_TREE, ancestor_id)
+                    });
+                }
+                Err(e) => return Err(e),
+            };
+            if !seen.insert(ancestor_id) {
+                let local = ancestor_taxons
+                    .iter()
+                    .any(|&t| self.overlay.contains_taxon(t));
+                return Err(if local {
+                    TaxonomyError::OverlayCycle(ancestor_id)
+                } else {
+                    TaxonomyError::corrupted(TAXON_TREE, ancestor_id)
+                });
+            }
+            ancestor_taxons.push(ancestor_id);
+        }
+        Ok(ancestor_taxons)
+    }
+
+    /// The ID, rank and scientific name of `taxon`.
+    pub fn taxon(&self, taxon: u32) -> TaxonomyResult<Taxon> {
+        Ok(Taxon {
+            id: taxon,
+            rank: self.rank(taxon)?,
+            name: self.name(taxon)?,
+        })
+    }
+
+    /// The lowest common ancestor of `taxa`: the most specific taxon that every one of them
+    /// belongs to. A taxon counts as belonging to itself, so the LCA of a single taxon is that
+    /// taxon. Merged taxa count as the taxon they were merged into. Returns `None` if `taxa` is
+    /// empty.
+    pub fn lca(&self, taxa: &[u32]) -> TaxonomyResult<Option<Taxon>> {
+        let (first, rest) = match taxa.split_first() {
+            Some(split) => split,
+            None => return Ok(None),
+        };
+        // Candidates, most specific first. Each further taxon can only cut this down to the
+        // candidates that are also its ancestors.
+        let mut common = self.ancestors(self.resolve_taxid(*first)?)?;
+        for taxon in rest {
+            let ancestors = self
+                .ancestors(self.resolve_taxid(*taxon)?)?
+                .into_iter()
+                .collect::<HashSet<_>>();
+            let lowest = common
+                .iter()
+                .position(|candidate| ancestors.contains(candidate))
+                .ok_or_else(|| TaxonomyError::corrupted(TAXON_TREE, taxon))?;
+            common.drain(..lowest);
+        }
+        self.taxon(common[0]).map(Some)
+    }
+
+    /// The ID of the taxon an accession belongs to, without looking up its lineage. This finds
+    /// accessions as [`TaxonomyDatabase::query_accession`] does, and doesn't follow merges.
+    pub fn accession_taxid(&self, accession: &str) -> TaxonomyResult<u32> {
+        Ok(self.accession_taxon(accession, LookupOptions::default())?.0)
+    }
+
+    /// Like [`TaxonomyDatabase::lca`], for the taxa that the given accessions belong to. Taxa
+    /// that accessions were recorded against but that have since been merged are followed.
+    pub fn lca_accessions(&self, accessions: &[&str]) -> TaxonomyResult<Option<Taxon>> {
+        let taxa = accessions
+            .iter()
+            .map(|accession| self.accession_taxid(accession))
+            .collect::<TaxonomyResult<Vec<_>>>()?;
+        self.lca(&taxa)
+    }
+
+    /// The current ID of `taxon`: either `taxon` itself, or, if NCBI has merged it into another
+    /// taxon, the ID of the taxon it was merged into. Apart from [`TaxonomyDatabase::query_taxon`],
+    /// [`TaxonomyDatabase::lca`], [`TaxonomyDatabase::is_descendant`] and
+    /// [`TaxonomyDatabase::subtree_size`], which do this themselves, methods taking a taxon
+    /// expect its current ID.
+    pub fn resolve_taxid(&self, taxon: u32) -> TaxonomyResult<u32> {
+        let mut current = taxon;
+        // Merges are normally recorded directly against the final taxon, but there's no harm in
+        // following a chain of them, as long as it doesn't loop.
+        let mut seen = HashSet::new();
+        while !self.overlay.contains_taxon(current)
+            && !self.taxon_tree.contains_key(current.to_le_bytes())?
+        {
+            if !seen.insert(current) {
+                return Err(TaxonomyError::corrupted(MERGED_TAXA, taxon));
+            }
+            current = match self.merged_taxa.get(current.to_le_bytes())? {
+                Some(content) => (*content)
+                    .try_into()
+                    .map(u32::from_le_bytes)
+                    .map_err(|_| TaxonomyError::corrupted(MERGED_TAXA, current))?,
+                None => return Err(self.missing_taxon(current)),
+            };
+        }
+        Ok(current)
+    }
+
+    pub fn query_taxon(&self, taxon: u32) -> TaxonomyResult<TaxonomyInfo> {
+        let current = self.resolve_taxid(taxon)?;
         let mut result = vec![];
+        let mut local = false;
 
-        for taxon in ancestor_taxons {
-            result.push((self.rank(taxon)?, self.name(taxon)?));
+        for taxon in self.ancestors(current)? {
+            local |= self.overlay.contains_taxon(taxon);
+            // The root isn't interesting enough to report.
+            if taxon == 1 {
+                break;
+            }
+            result.push(self.taxon(taxon)?);
         }
 
-        Ok(TaxonomyInfo(result))
+        Ok(TaxonomyInfo {
+            lineage: Lineage::new(result),
+            merged_from: (current != taxon).then_some(taxon),
+            local,
+        })
+    }
+
+    /// Fail with [`TaxonomyError::RetiredAccession`] if the accession has been retired.
+    fn check_retired(&self, accession: &str, bare_acc: &str) -> TaxonomyResult<()> {
+        match self.dead_accessions.get(bare_acc)? {
+            Some(node) => Err(TaxonomyError::RetiredAccession {
+                accession: accession.to_owned(),
+                taxon: parse_taxid(&node)
+                    .ok_or_else(|| TaxonomyError::corrupted(DEAD_ACCESSIONS, accession))?,
+            }),
+            None => Ok(()),
+        }
     }
 
-    pub fn query_accession(&self, accession: &str) -> std::io::Result<TaxonomyInfo> {
-        let bare_acc = accession.split('.').next().unwrap().as_bytes();
+    /// The taxon that an accession belongs to, how we found it, and which version of the
+    /// accession matched, if known.
+    fn accession_taxon(
+        &self,
+        accession: &str,
+        options: LookupOptions,
+    ) -> TaxonomyResult<(u32, AccessionMatch, Option<u32>)> {
+        let (bare_acc, version) = split_accession_version(accession);
 
-        let taxon_vec = if let Some(node) = self.accession_to_taxon.get(&bare_acc)? {
-            node
+        if let Some(taxon) = self.overlay.accession_taxon(bare_acc) {
+            return Ok((taxon, AccessionMatch::Local, None));
+        }
+
+        if options.exact_version {
+            let versions = self
+                .accession_versions
+                .as_ref()
+                .ok_or(TaxonomyError::AccessionVersionsUnavailable)?;
+            let version = version.ok_or_else(|| TaxonomyError::NotFound(accession.to_owned()))?;
+            if let Some(node) = versions.get(accession_version_key(bare_acc, version))? {
+                let taxon = parse_taxid(&node)
+                    .ok_or_else(|| TaxonomyError::corrupted(ACCESSION_VERSIONS, accession))?;
+                return Ok((taxon, AccessionMatch::Exact, Some(version)));
+            }
+            self.check_retired(accession, bare_acc)?;
+            return Err(TaxonomyError::NotFound(accession.to_owned()));
+        }
+
+        let (taxon, accession_match) = self.bare_accession_taxon(accession, bare_acc, options)?;
+        let matched_version = self.matched_version(bare_acc, version, taxon)?;
+        Ok((taxon, accession_match, matched_version))
+    }
+
+    /// The recorded range of accessions that an accession falls within, if any.
+    fn range_taxon(
+        &self,
+        accession: &str,
+        bare_acc: &str,
+    ) -> TaxonomyResult<Option<(u32, AccessionMatch)>> {
+        // Ranges don't overlap in practice, so only the closest one starting at or before the
+        // accession can hold it.
+        let (start, value) = match self
+            .accession_ranges
+            .range(..=bare_acc.as_bytes())
+            .next_back()
+        {
+            Some(entry) => entry?,
+            None => return Ok(None),
+        };
+        let (taxon, end) = parse_range_value(&value)
+            .ok_or_else(|| TaxonomyError::corrupted(ACCESSION_RANGES, accession))?;
+        if start.len() != bare_acc.len() || bare_acc.as_bytes() > end {
+            return Ok(None);
+        }
+        let accession_match = AccessionMatch::Range {
+            start: String::from_utf8_lossy(&start).into_owned(),
+            end: String::from_utf8_lossy(end).into_owned(),
+        };
+        Ok(Some((taxon, accession_match)))
+    }
+
+    /// The taxon that an accession belongs to, ignoring its version, and how we found it. In
+    /// strict mode, accessions that fall between two endstops of a run are only accepted if
+    /// they're known to really exist; accessions within a recorded range always are.
+    fn bare_accession_taxon(
+        &self,
+        accession: &str,
+        bare_acc: &str,
+        options: LookupOptions,
+    ) -> TaxonomyResult<(u32, AccessionMatch)> {
+        let (taxon_vec, accession_match) = if let Some(node) =
+            self.accession_to_taxon.get(bare_acc)?
+        {
+            (node, AccessionMatch::Exact)
         } else {
+            self.check_retired(accession, bare_acc)?;
+            if let Some(found) = self.range_taxon(accession, bare_acc)? {
+                return Ok(found);
+            }
+            if !self.interpolate {
+                return Err(TaxonomyError::NotFound(accession.to_owned()));
+            }
+            let confirmed = match &self.accession_set {
+                Some(accession_set) => Some(accession_set.contains_key(bare_acc)?),
+                None => None,
+            };
+            match (options.strict, confirmed) {
+                (true, None) => return Err(TaxonomyError::ExactMembershipUnavailable),
+                (true, Some(false)) => return Err(TaxonomyError::NotFound(accession.to_owned())),
+                _ => {}
+            }
             match (
-                self.accession_to_taxon.get_lt(&bare_acc)?,
-                self.accession_to_taxon.get_gt(&bare_acc)?,
+                self.accession_to_taxon.get_lt(bare_acc)?,
+                self.accession_to_taxon.get_gt(bare_acc)?,
             ) {
-                (Some((_, lbs)), Some((_, rbs))) if lbs == rbs => lbs,
-                _ => {
-                    return Err(std::io::Error::new(
-                        std::io::ErrorKind::NotFound,
-                        "Accession number not found in database",
-                    ))
-                }
+                (Some((lower, lbs)), Some((upper, rbs))) if lbs == rbs => (
+                    lbs,
+                    AccessionMatch::Interpolated {
+                        lower: String::from_utf8_lossy(&lower).into_owned(),
+                        upper: String::from_utf8_lossy(&upper).into_owned(),
+                        confirmed,
+                    },
+                ),
+                _ => return Err(TaxonomyError::NotFound(accession.to_owned())),
             }
         };
 
-        let taxon_bytes: [u8; 4] = (*taxon_vec).try_into().map_err(|_| {
-            data_error("Corrupted taxonomy node information: Could not get taxon bytes")
-        })?;
+        let taxon = parse_taxid(&taxon_vec)
+            .ok_or_else(|| TaxonomyError::corrupted(ACCESSION_TO_TAXON, accession))?;
+        Ok((taxon, accession_match))
+    }
+
+    /// Which version of an accession a version-agnostic lookup matched: the version asked for,
+    /// if it belongs to the same taxon, or otherwise the latest version that does. `None` if the
+    /// database doesn't record versions.
+    fn matched_version(
+        &self,
+        bare_acc: &str,
+        wanted: Option<u32>,
+        taxon: u32,
+    ) -> TaxonomyResult<Option<u32>> {
+        let versions = match &self.accession_versions {
+            Some(versions) => versions,
+            None => return Ok(None),
+        };
+        let mut prefix = bare_acc.as_bytes().to_vec();
+        prefix.push(0);
+        let mut matched = None;
+        for entry in versions.scan_prefix(prefix) {
+            let (key, value) = entry?;
+            let version_bytes = key[key.len() - 4..]
+                .try_into()
+                .map_err(|_| TaxonomyError::corrupted(ACCESSION_VERSIONS, bare_acc))?;
+            let version = u32::from_be_bytes(version_bytes);
+            if parse_taxid(&value) != Some(taxon) {
+                continue;
+            }
+            if Some(version) == wanted {
+                return Ok(Some(version));
+            }
+            matched = Some(version);
+        }
+        Ok(matched)
+    }
+
+    /// Look up the taxonomy of an accession, ignoring its version. To keep the database
+    /// compact, only the first and last of each run of accessions sharing a taxon are stored, so
+    /// an accession that doesn't exist at all is still answered if it happens to fall inside
+    /// such a run. Databases built from sources with sparse accessions, like GTDB, store every
+    /// accession and only answer for those.
+    pub fn query_accession(&self, accession: &str) -> TaxonomyResult<TaxonomyInfo> {
+        self.accession_info(accession, LookupOptions::default())
+    }
+
+    /// Like [`TaxonomyDatabase::query_accession`], but only answers for accessions that were
+    /// actually present in the source files. This needs a database built with
+    /// [`TaxonomyDatabaseConfig::exact_membership`].
+    pub fn query_accession_strict(&self, accession: &str) -> TaxonomyResult<TaxonomyInfo> {
+        let options = LookupOptions {
+            strict: true,
+            ..LookupOptions::default()
+        };
+        self.accession_info(accession, options)
+    }
+
+    /// Look up the taxonomy of one specific version of an accession, e.g. "U39076.1". This
+    /// needs a database built with [`TaxonomyDatabaseConfig::accession_versions`].
+    pub fn query_accession_version(&self, accession: &str) -> TaxonomyResult<TaxonomyInfo> {
+        let options = LookupOptions {
+            exact_version: true,
+            ..LookupOptions::default()
+        };
+        self.accession_info(accession, options)
+    }
+
+    /// The taxonomy of an accession, marked as local if the overlay assigned it.
+    fn accession_info(
+        &self,
+        accession: &str,
+        options: LookupOptions,
+    ) -> TaxonomyResult<TaxonomyInfo> {
+        let (taxon, accession_match, _) = self.accession_taxon(accession, options)?;
+        let mut info = self.query_taxon(taxon)?;
+        info.local |= accession_match == AccessionMatch::Local;
+        Ok(info)
+    }
+
+    /// Look up an accession as the other `query_accession` methods do, depending on `options`,
+    /// but also explain how the answer was reached, so that callers can judge how far to trust
+    /// it.
+    pub fn query_accession_explained(
+        &self,
+        accession: &str,
+        options: LookupOptions,
+    ) -> TaxonomyResult<(TaxonomyInfo, LookupExplanation)> {
+        let (taxon, accession_match, version) = self.accession_taxon(accession, options)?;
+        let mut info = self.query_taxon(taxon)?;
+        info.local |= accession_match == AccessionMatch::Local;
+        let explanation = LookupExplanation {
+            accession_match,
+            version,
+            taxon,
+            merged_into: match info.merged_from {
+                Some(_) => Some(self.resolve_taxid(taxon)?),
+                None => None,
+            },
+        };
+        Ok((info, explanation))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::SourceStream;
+
+    /// A source with just a tree of taxa, each named after its ID.
+    struct TreeSource(Vec<(u32, u32, Rank)>);
+
+    impl TaxonomySource for TreeSource {
+        fn nodes(&self) -> TaxonomyResult<SourceStream<'_, (u32, u32, Rank)>> {
+            Ok(Box::new(self.0.clone().into_iter().map(Ok)))
+        }
+
+        fn names(&self) -> TaxonomyResult<SourceStream<'_, (u32, NameClass, String)>> {
+            Ok(Box::new(self.0.iter().map(|&(taxon, _, _)| {
+                Ok((taxon, NameClass::ScientificName, taxon.to_string()))
+            })))
+        }
+
+        fn accessions(&self) -> TaxonomyResult<SourceStream<'_, (String, Option<u32>, u32)>> {
+            Ok(Box::new(std::iter::empty()))
+        }
+    }
+
+    /// A database of `nodes`, kept in a directory that lasts as long as the returned guard.
+    fn build(nodes: &[(u32, u32, Rank)]) -> (tempfile::TempDir, TaxonomyDatabase) {
+        let dir = tempfile::tempdir().unwrap();
+        let db = TaxonomyDatabaseConfig::new()
+            .location(dir.path().join("db"))
+            .source(TaxonomyDatabaseSource::FromSource(Box::new(TreeSource(
+                nodes.to_vec(),
+            ))))
+            .build()
+            .unwrap();
+        (dir, db)
+    }
+
+    /// A [`TreeSource`] whose accessions mustn't be interpolated between.
+    struct SparseSource(TreeSource);
+
+    impl TaxonomySource for SparseSource {
+        fn nodes(&self) -> TaxonomyResult<SourceStream<'_, (u32, u32, Rank)>> {
+            self.0.nodes()
+        }
+
+        fn names(&self) -> TaxonomyResult<SourceStream<'_, (u32, NameClass, String)>> {
+            self.0.names()
+        }
+
+        fn accessions(&self) -> TaxonomyResult<SourceStream<'_, (String, Option<u32>, u32)>> {
+            self.0.accessions()
+        }
+
+        fn interpolate_accessions(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn databases_that_dont_interpolate_have_their_own_version() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let source = SparseSource(TreeSource(small_tree()));
+        assert!(
+            !build_new_db(db.clone(), &source, &BuildOptions::default())
+                .unwrap()
+                .interpolate
+        );
+        assert_eq!(
+            db.get(TAXONOMY_DB_VERSION_KEY).unwrap().unwrap(),
+            NO_INTERPOLATION_DB_VERSION
+        );
+        assert!(!open_existing(db.clone()).unwrap().interpolate);
+
+        db.insert(TAXONOMY_DB_VERSION_KEY, b"3").unwrap();
+        assert!(matches!(
+            open_existing(db),
+            Err(TaxonomyError::IncompatibleVersion { .. })
+        ));
+    }
+
+    /// root -> 2 -> {3 -> {5, 6}, 4}
+    fn small_tree() -> Vec<(u32, u32, Rank)> {
+        vec![
+            (1, 1, Rank::NoRank),
+            (2, 1, Rank::Superkingdom),
+            (3, 2, Rank::Genus),
+            (4, 2, Rank::Genus),
+            (5, 3, Rank::Species),
+            (6, 3, Rank::Species),
+        ]
+    }
+
+    fn descendants(db: &TaxonomyDatabase, taxon: u32, max_depth: usize) -> Vec<u32> {
+        db.descendants(taxon)
+            .unwrap()
+            .max_depth(max_depth)
+            .collect::<TaxonomyResult<Vec<_>>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn max_depth_counts_from_the_children() {
+        let (_dir, db) = build(&small_tree());
+        assert_eq!(descendants(&db, 2, 0), Vec::<u32>::new());
+        assert_eq!(descendants(&db, 2, 1), vec![3, 4]);
+        assert_eq!(descendants(&db, 2, 2), vec![3, 5, 6, 4]);
+        assert_eq!(descendants(&db, 2, 5), vec![3, 5, 6, 4]);
+    }
+
+    #[test]
+    fn overlay_accessions_need_a_known_taxon() {
+        let (_dir, mut db) = build(&small_tree());
+        let overlay = Overlay::read_tsv(
+            "taxon\t900\t3\tspecies\tLocal\n\
+             accession\tPRIV0001\t900\n\
+             accession\tPRIV0002\t5\n\
+             accession\tPRIV0003\t901\n\
+             accession\tPRIV0004\t902\n"
+                .as_bytes(),
+            Path::new("overlay.tsv"),
+        )
+        .unwrap();
+        assert!(matches!(
+            db.set_overlay(overlay),
+            Err(TaxonomyError::Source { line: 4, .. })
+        ));
+        assert!(db.overlay().is_empty());
+
+        let mut overlay = Overlay::new();
+        overlay.add_taxon(900, 3, Rank::Species, "Local").unwrap();
+        overlay.add_accession("PRIV0001", 900);
+        overlay.add_accession("PRIV0002", 5);
+        db.set_overlay(overlay.clone()).unwrap();
+        overlay.add_accession("PRIV0003", 901);
+        assert!(matches!(
+            db.set_overlay(overlay),
+            Err(TaxonomyError::UnknownTaxon(901))
+        ));
+        assert!(db.overlay().contains_taxon(900));
+    }
+
+    #[test]
+    fn export_ignores_the_overlay() {
+        let (_dir, mut db) = build(&small_tree());
+        let mut overlay = Overlay::new();
+        overlay.add_taxon(3, 4, Rank::Family, "Replaced").unwrap();
+        overlay.add_taxon(900, 3, Rank::Species, "Local").unwrap();
+        db.set_overlay(overlay).unwrap();
+        let export = crate::TaxonomyExport::new(&db);
+        let mut nodes = vec![];
+        export.write_nodes(&mut nodes).unwrap();
+        let nodes = String::from_utf8(nodes).unwrap();
+        assert!(nodes.contains("\n3\t|\t2\t|\tgenus\t|"));
+        assert!(!nodes.contains("900"));
+        let mut names = vec![];
+        export.write_names(&mut names).unwrap();
+        let names = String::from_utf8(names).unwrap();
+        assert!(names.contains("\n3\t|\t3\t|"));
+        assert!(!names.contains("Replaced") && !names.contains("Local"));
+        // The database itself still answers from the overlay.
+        assert_eq!(db.parent(3).unwrap(), 4);
+    }
+
+    fn node_tree(nodes: &[(u32, u32, Rank)]) -> BTreeMap<u32, (u32, Rank)> {
+        nodes
+            .iter()
+            .map(|&(taxon, parent, rank)| (taxon, (parent, rank)))
+            .collect()
+    }
+
+    #[test]
+    fn intervals_nest_and_siblings_are_disjoint() {
+        let intervals = dfs_intervals(&node_tree(&small_tree()));
+        assert_eq!(intervals[&1], (0, 5));
+        assert_eq!(intervals[&2], (1, 5));
+        let (a, b) = (intervals[&3], intervals[&4]);
+        assert!(a.1 < b.0 || b.1 < a.0, "{:?} and {:?} overlap", a, b);
+        for leaf in [4, 5, 6] {
+            assert_eq!(intervals[&leaf].0, intervals[&leaf].1);
+        }
+    }
+
+    #[test]
+    fn deep_chains_get_nested_intervals() {
+        // Deep enough that a recursive walk would be in trouble.
+        let depth = 100_000;
+        let mut nodes = vec![(1, 1, Rank::NoRank)];
+        nodes.extend((2..=depth).map(|taxon| (taxon, taxon - 1, Rank::NoRank)));
+        let intervals = dfs_intervals(&node_tree(&nodes));
+        for taxon in 1..=depth {
+            assert_eq!(intervals[&taxon], (taxon - 1, depth - 1));
+        }
+    }
+
+    #[test]
+    fn is_descendant_follows_the_tree() {
+        let (_dir, db) = build(&small_tree());
+        for taxon in 1..=6 {
+            assert!(db.is_descendant(taxon, taxon).unwrap());
+            assert!(db.is_descendant(taxon, 1).unwrap());
+        }
+        assert!(db.is_descendant(5, 3).unwrap());
+        assert!(db.is_descendant(5, 2).unwrap());
+        assert!(!db.is_descendant(5, 4).unwrap());
+        assert!(!db.is_descendant(3, 4).unwrap());
+        assert!(!db.is_descendant(4, 3).unwrap());
+        assert!(!db.is_descendant(2, 5).unwrap());
+        assert!(!db.is_descendant(5, 6).unwrap());
+        assert!(matches!(
+            db.is_descendant(7, 1),
+            Err(TaxonomyError::UnknownTaxon(7))
+        ));
+    }
+
+    #[test]
+    fn is_descendant_works_along_a_chain() {
+        let mut nodes = vec![(1, 1, Rank::NoRank)];
+        nodes.extend((2..=50).map(|taxon| (taxon, taxon - 1, Rank::NoRank)));
+        nodes.push((100, 1, Rank::NoRank));
+        let (_dir, db) = build(&nodes);
+        assert!(db.is_descendant(50, 2).unwrap());
+        assert!(db.is_descendant(50, 49).unwrap());
+        assert!(!db.is_descendant(49, 50).unwrap());
+        assert!(!db.is_descendant(50, 100).unwrap());
+        assert_eq!(db.subtree_size(2).unwrap(), 49);
+    }
+
+    #[test]
+    fn ranges_split_into_their_ends() {
+        assert_eq!(
+            split_accession_range("AAAA01000001-AAAA01099999"),
+            Some(("AAAA01000001", "AAAA01099999"))
+        );
+        assert_eq!(
+            split_accession_range("U40001-U40003"),
+            Some(("U40001", "U40003"))
+        );
+        assert_eq!(
+            split_accession_range("U40001-U40001"),
+            Some(("U40001", "U40001"))
+        );
+    }
+
+    #[test]
+    fn ranges_need_matching_prefixes() {
+        assert_eq!(split_accession_range("AAAA01000001-AAAB01099999"), None);
+        assert_eq!(split_accession_range("U40001-UU0003"), None);
+        assert_eq!(split_accession_range("U40001-X40003"), None);
+    }
+
+    #[test]
+    fn ranges_need_matching_digit_widths() {
+        assert_eq!(split_accession_range("U40001-U400003"), None);
+        assert_eq!(split_accession_range("U4001-U40003"), None);
+    }
 
-        self.query_taxon(u32::from_le_bytes(taxon_bytes))
+    #[test]
+    fn reversed_and_empty_ranges_are_rejected() {
+        assert_eq!(split_accession_range("U40003-U40001"), None);
+        assert_eq!(split_accession_range("-U40001"), None);
+        assert_eq!(split_accession_range("U40001-"), None);
+        assert_eq!(split_accession_range("U40001"), None);
     }
 }
@@ -0,0 +1,132 @@
+use crate::error::{TaxonomyError, TaxonomyResult};
+use crate::taxonomy_db::{LookupExplanation, LookupOptions, TaxonomyDatabase, TaxonomyInfo};
+
+/// An answer from a [`TaxonomyDatabaseSet`], and the label of the database that gave it.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SetAnswer<'a, T> {
+    pub database: &'a str,
+    pub answer: T,
+}
+
+/// Several databases, consulted in priority order: each query is answered by the first
+/// database that can answer it. A database that has only heard of an accession or taxon as
+/// retired or deleted doesn't count as answering, but that's what's reported if no other
+/// database answers either.
+#[derive(Default)]
+pub struct TaxonomyDatabaseSet {
+    databases: Vec<(String, TaxonomyDatabase)>,
+}
+
+/// Whether the error just means that the database couldn't answer, so that the next one should
+/// be asked.
+fn is_miss(e: &TaxonomyError) -> bool {
+    matches!(
+        e,
+        TaxonomyError::NotFound(_)
+            | TaxonomyError::UnknownTaxon(_)
+            | TaxonomyError::RetiredAccession { .. }
+            | TaxonomyError::DeletedTaxon(_)
+            | TaxonomyError::ExactMembershipUnavailable
+            | TaxonomyError::AccessionVersionsUnavailable
+            | TaxonomyError::IndexUnavailable(_)
+    )
+}
+
+/// Whether the error says nothing more than that the database has never heard of the query.
+fn is_plain_miss(e: &TaxonomyError) -> bool {
+    matches!(
+        e,
+        TaxonomyError::NotFound(_) | TaxonomyError::UnknownTaxon(_)
+    )
+}
+
+impl TaxonomyDatabaseSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a database, to be consulted after those already added. `label` identifies it in
+    /// answers.
+    pub fn add(mut self, label: impl Into<String>, db: TaxonomyDatabase) -> Self {
+        self.databases.push((label.into(), db));
+        self
+    }
+
+    /// The databases, with their labels, in priority order.
+    pub fn databases(&self) -> impl Iterator<Item = (&str, &TaxonomyDatabase)> {
+        self.databases
+            .iter()
+            .map(|(label, db)| (label.as_str(), db))
+    }
+
+    pub fn databases_mut(&mut self) -> impl Iterator<Item = (&str, &mut TaxonomyDatabase)> {
+        self.databases
+            .iter_mut()
+            .map(|(label, db)| (label.as_str(), db))
+    }
+
+    pub fn len(&self) -> usize {
+        self.databases.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.databases.is_empty()
+    }
+
+    /// Ask each database in turn, until one answers. If none does, report the most informative
+    /// of their errors, or `empty` if there are no databases.
+    fn first_answer<T>(
+        &self,
+        empty: TaxonomyError,
+        query: impl Fn(&TaxonomyDatabase) -> TaxonomyResult<T>,
+    ) -> TaxonomyResult<SetAnswer<'_, T>> {
+        let mut miss: Option<TaxonomyError> = None;
+        for (label, db) in &self.databases {
+            match query(db) {
+                Ok(answer) => {
+                    return Ok(SetAnswer {
+                        database: label,
+                        answer,
+                    })
+                }
+                Err(e) if is_miss(&e) => {
+                    let more_informative = match &miss {
+                        Some(miss) => is_plain_miss(miss) && !is_plain_miss(&e),
+                        None => true,
+                    };
+                    if more_informative {
+                        miss = Some(e);
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Err(miss.unwrap_or(empty))
+    }
+
+    /// Like [`TaxonomyDatabase::query_accession`], against each database in turn.
+    pub fn query_accession(&self, accession: &str) -> TaxonomyResult<SetAnswer<'_, TaxonomyInfo>> {
+        self.first_answer(TaxonomyError::NotFound(accession.to_owned()), |db| {
+            db.query_accession(accession)
+        })
+    }
+
+    /// Like [`TaxonomyDatabase::query_accession_explained`], against each database in turn.
+    pub fn query_accession_explained(
+        &self,
+        accession: &str,
+        options: LookupOptions,
+    ) -> TaxonomyResult<SetAnswer<'_, (TaxonomyInfo, LookupExplanation)>> {
+        self.first_answer(TaxonomyError::NotFound(accession.to_owned()), |db| {
+            db.query_accession_explained(accession, options)
+        })
+    }
+
+    /// Like [`TaxonomyDatabase::query_taxon`], against each database in turn.
+    pub fn query_taxon(&self, taxon: u32) -> TaxonomyResult<SetAnswer<'_, TaxonomyInfo>> {
+        self.first_answer(TaxonomyError::UnknownTaxon(taxon), |db| {
+            db.query_taxon(taxon)
+        })
+    }
+}
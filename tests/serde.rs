@@ -0,0 +1,137 @@
+#![cfg(feature = "serde")]
+
+use std::fmt::Debug;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use taxonomy_lookup::{
+    AccessionMatch, FuzzyMatch, Lineage, LookupExplanation, NameClass, NameMatch, Rank, Taxon,
+    TaxonomyChange, TaxonomyInfo,
+};
+
+fn round_trip<T: Serialize + DeserializeOwned + PartialEq + Debug>(value: &T) -> String {
+    let json = serde_json::to_string(value).unwrap();
+    let back: T = serde_json::from_str(&json).unwrap();
+    assert_eq!(&back, value, "{}", json);
+    json
+}
+
+fn taxon(id: u32, rank: Rank, name: &str) -> Taxon {
+    Taxon {
+        id,
+        rank,
+        name: name.to_string(),
+    }
+}
+
+fn e_coli() -> Lineage {
+    Lineage::new(vec![
+        taxon(562, Rank::Species, "Escherichia coli"),
+        taxon(561, Rank::Genus, "Escherichia"),
+        taxon(2, Rank::Superkingdom, "Bacteria"),
+    ])
+}
+
+#[test]
+fn ranks_use_ncbi_names() {
+    for rank in Rank::all() {
+        let name: &str = rank.into();
+        assert_eq!(round_trip(&rank), format!("\"{}\"", name));
+    }
+    assert_eq!(
+        serde_json::to_string(&Rank::SpeciesGroup).unwrap(),
+        "\"species group\""
+    );
+    assert!(serde_json::from_str::<Rank>("\"SpeciesGroup\"").is_err());
+}
+
+#[test]
+fn name_classes_use_ncbi_names() {
+    for class in (0..=u8::MAX).filter_map(|n| NameClass::try_from(n).ok()) {
+        let name: &str = class.into();
+        assert_eq!(round_trip(&class), format!("\"{}\"", name));
+    }
+    assert!(serde_json::from_str::<NameClass>("\"nickname\"").is_err());
+}
+
+#[test]
+fn lineage_is_a_list_of_taxa() {
+    let json = round_trip(&e_coli());
+    assert!(json.starts_with(r#"[{"id":562,"rank":"species","name":"Escherichia coli"}"#));
+}
+
+#[test]
+fn taxonomy_info_round_trips() {
+    round_trip(&TaxonomyInfo {
+        lineage: e_coli(),
+        merged_from: Some(12),
+        local: true,
+    });
+}
+
+#[test]
+fn lookup_explanations_round_trip() {
+    let matches = [
+        AccessionMatch::Exact,
+        AccessionMatch::Interpolated {
+            lower: "A00001".to_string(),
+            upper: "A00005".to_string(),
+            confirmed: Some(true),
+        },
+        AccessionMatch::Range {
+            start: "AAAA01000001".to_string(),
+            end: "AAAA01099999".to_string(),
+        },
+        AccessionMatch::Local,
+    ];
+    for accession_match in matches {
+        round_trip(&LookupExplanation {
+            accession_match,
+            version: Some(2),
+            taxon: 12,
+            merged_into: Some(562),
+        });
+    }
+}
+
+#[test]
+fn name_matches_round_trip() {
+    round_trip(&NameMatch {
+        taxon: 562,
+        name: "Escherichia coli".to_string(),
+        name_class: NameClass::ScientificName,
+        rank: Rank::Species,
+    });
+    round_trip(&FuzzyMatch {
+        taxon: 562,
+        name: "Escherichia coli".to_string(),
+        name_class: NameClass::ScientificName,
+        rank: Rank::Species,
+        score: 0.875,
+        lineage: e_coli(),
+    });
+}
+
+#[test]
+fn changes_round_trip() {
+    assert_eq!(
+        round_trip(&TaxonomyChange::RankChanged {
+            taxon: 83333,
+            old: Rank::NoRank,
+            new: Rank::Strain,
+        }),
+        r#"{"change":"rank_changed","taxon":83333,"old":"no rank","new":"strain"}"#
+    );
+    assert_eq!(
+        round_trip(&TaxonomyChange::AccessionChanged {
+            accession: "U39076".to_string(),
+            old: 10244,
+            new: 10245,
+        }),
+        r#"{"change":"accession_changed","accession":"U39076","old":10244,"new":10245}"#
+    );
+    round_trip(&TaxonomyChange::TaxonMerged {
+        taxon: 1392,
+        into: 562,
+    });
+}